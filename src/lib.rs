@@ -1,19 +1,71 @@
-use earcutr;
 use fontdue::{Font, FontSettings, Metrics};
-use image::{ImageBuffer, Pixel, Rgb};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::{Delay, Frame, ImageBuffer, ImageFormat, Rgb, Rgba};
 use minifb::{Key, KeyRepeat, MouseButton, MouseMode};
-use rand;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
 use std::path::Path;
-use std::time::Duration;
+use std::rc::Rc;
 
 const DEFAULT_NAME: &str = "Rust Render 101 Sketch";
 
+/// Pixel size at which text is rasterized
+const TEXT_SIZE: f32 = 32f32;
+
+/// Largest accepted sketch width or height
+const MAX_DIMENSION: usize = 16384;
+
+/// Smallest number of pixels for which fills and filters are spread across threads
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_PIXELS: usize = 1 << 16;
+
+/// Ken Perlin's reference permutation table, used by Geometry noise functions
+const PERLIN_PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225,
+    140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148,
+    247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32,
+    57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122,
+    60, 211, 133, 230, 220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54,
+    65, 25, 63, 161, 1, 216, 80, 73, 209, 76, 132, 187, 208, 89, 18, 169,
+    200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173, 186, 3, 64,
+    52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212,
+    207, 206, 59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213,
+    119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101, 155, 167, 43, 172, 9,
+    129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232, 178, 185, 112, 104,
+    218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162, 241,
+    81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157,
+    184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93,
+    222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+#[derive(Clone)]
 pub enum StrokeMode {
     Circle,
     Square,
-    Custom(fn(i8) -> Vec<(i8, i8)>),
+    Custom(fn(i32) -> Vec<(i32, i32)>),
+}
+
+/// Shape of line ends: Butt cuts the stroke flat at the endpoints, Square extends it by the stroke weight,
+/// Round (the default) keeps the whole stroke mask at the endpoints, so ends follow the stroke mode
+/// (round for StrokeMode::Circle) and lines look as they did before caps existed
+#[derive(Clone)]
+pub enum StrokeCap {
+    Butt,
+    Round,
+    Square,
+}
+
+/// Shape of the corners where connected segments of a polyline, spline, polygon, rect or triangle stroke meet:
+/// Miter extends the outer edges to a point (beveled past 4 half widths), Round stamps a disc, Bevel cuts it flat
+#[derive(Clone, Copy, PartialEq)]
+pub enum StrokeJoin {
+    Miter,
+    Round,
+    Bevel,
 }
 
 pub enum FontMode {
@@ -26,6 +78,53 @@ pub enum ShapeType {
     Polygon,
     LinearSpline { loops: bool },
     CubicBezierSpline { loops: bool },
+    QuadraticBezierSpline { loops: bool },
+}
+
+#[derive(Clone)]
+pub enum BlendMode {
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+}
+
+#[derive(Debug)]
+pub enum ShapeError {
+    Triangulation(String),
+}
+
+impl std::fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShapeError::Triangulation(message) => write!(f, "Triangulation error: {}", message),
+        }
+    }
+}
+
+pub enum PolygonFillMethod {
+    Scanline,
+    Triangulation,
+}
+
+/// Orientation of the y axis used by drawing primitives, see Sketch::coordinate_mode
+#[derive(Clone, Copy, PartialEq)]
+pub enum CoordMode {
+    YDown,
+    YUp,
+}
+
+/// Decides which regions of a self-overlapping polygon are inside: EvenOdd fills regions crossed by an
+/// odd number of edges, NonZero fills regions with a non-zero winding number
+#[derive(Clone, Copy, PartialEq)]
+pub enum FillRule {
+    EvenOdd,
+    NonZero,
+}
+
+pub enum ImageSampling {
+    Nearest,
+    Bilinear,
 }
 
 pub struct RgbaColor {}
@@ -79,6 +178,28 @@ impl RgbaColor {
         color as u8
     }
 
+    /// Linearly interpolates every channel (alpha included) between color_a (t = 0) and color_b (t = 1)
+    pub fn lerp_color(color_a: u32, color_b: u32, t: f32) -> u32 {
+        let t = t.clamp(0.0, 1.0);
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+        RgbaColor::argb_color(
+            lerp(RgbaColor::color_alpha(color_a), RgbaColor::color_alpha(color_b)),
+            lerp(RgbaColor::color_red(color_a), RgbaColor::color_red(color_b)),
+            lerp(RgbaColor::color_green(color_a), RgbaColor::color_green(color_b)),
+            lerp(RgbaColor::color_blue(color_a), RgbaColor::color_blue(color_b)),
+        )
+    }
+
+    /// Converts a color to gray using its luminance (0.299 * red + 0.587 * green + 0.114 * blue), keeping its alpha
+    pub fn grayscale_color(color: u32) -> u32 {
+        let luminance = (0.299 * RgbaColor::color_red(color) as f32
+            + 0.587 * RgbaColor::color_green(color) as f32
+            + 0.114 * RgbaColor::color_blue(color) as f32).round() as u8;
+
+        RgbaColor::argb_color(RgbaColor::color_alpha(color), luminance, luminance, luminance)
+    }
+
     /// Converts a u32 color to a tuple of 4 f32's between 0 and 1
     fn color_u32_to_4xf32(color: u32) -> (f32, f32, f32, f32) {
         (
@@ -89,13 +210,14 @@ impl RgbaColor {
         )
     }
 
-    /// Converts a tuple of 4 f32's to a u32 color
+    /// Converts a tuple of 4 f32's to a u32 color (rounded to nearest, so repeated compositing does not drift)
     fn color_4xf32_to_u32(color: (f32, f32, f32, f32)) -> u32 {
+        let channel = |c: f32| (c * 255f32).round().clamp(0f32, 255f32) as u8;
         RgbaColor::argb_color(
-            (color.0 * 255f32) as u8,
-            (color.1 * 255f32) as u8,
-            (color.2 * 255f32) as u8,
-            (color.3 * 255f32) as u8,
+            channel(color.0),
+            channel(color.1),
+            channel(color.2),
+            channel(color.3),
         )
     }
 
@@ -127,6 +249,51 @@ impl RgbaColor {
 
         RgbaColor::color_4xf32_to_u32((result_a, result_r, result_g, result_b))
     }
+
+    /// Blends a single channel of color_q onto color_p using a non-normal blend mode
+    fn blend_channel(mode: &BlendMode, p_c: f32, q_c: f32) -> f32 {
+        match mode {
+            BlendMode::Normal => q_c,
+            BlendMode::Add => (p_c + q_c).min(1f32),
+            BlendMode::Multiply => p_c * q_c,
+            BlendMode::Screen => 1f32 - (1f32 - p_c) * (1f32 - q_c),
+        }
+    }
+
+    /// Composes color_q onto color_p using a blend mode, the alpha of color_q controls the blend strength.
+    /// If linear is set, color channels are converted to linear light (c^2.2) before blending and back after,
+    /// which is more accurate for semi-transparent overlaps but costs 9 powf per blended pixel
+    fn color_blend_color(color_p: u32, color_q: u32, mode: &BlendMode, linear: bool) -> u32 {
+        if let (BlendMode::Normal, false) = (mode, linear) {
+            return RgbaColor::color_alpha_compose_color(color_p, color_q);
+        }
+
+        let (p_a, p_r, p_g, p_b) = RgbaColor::color_u32_to_4xf32(color_p);
+        let (q_a, q_r, q_g, q_b) = RgbaColor::color_u32_to_4xf32(color_q);
+
+        let result_a = RgbaColor::alpha_compose_alpha(p_a, q_a);
+        if result_a <= 0.0001f32 {
+            return RgbaColor::argb_color(0, 0, 0, 0);
+        }
+
+        let to_linear = |c: f32| if linear {c.powf(2.2)} else {c};
+        let from_linear = |c: f32| if linear {c.powf(1f32 / 2.2)} else {c};
+
+        let mix = |p_c: f32, q_c: f32| {
+            let (p_c, q_c) = (to_linear(p_c), to_linear(q_c));
+            from_linear(match mode {
+                BlendMode::Normal => RgbaColor::alpha_compose_channel(p_a, p_c, q_a, q_c, result_a),
+                _ => p_c * (1f32 - q_a) + RgbaColor::blend_channel(mode, p_c, q_c) * q_a,
+            })
+        };
+
+        RgbaColor::color_4xf32_to_u32((
+            result_a,
+            mix(p_r, q_r),
+            mix(p_g, q_g),
+            mix(p_b, q_b),
+        ))
+    }
 }
 
 pub enum EasingType {
@@ -157,6 +324,22 @@ pub enum TransitionTarget {
     Point { point: (i32, i32) },
 }
 impl TransitionTarget {
+    /// Returns the points if the target is a points vec
+    fn points(&self) -> Option<&Vec<(i32, i32)>> {
+        match self {
+            TransitionTarget::Points { points } => Some(points),
+            _ => None,
+        }
+    }
+
+    /// Returns the point if the target is a single point
+    fn point(&self) -> Option<&(i32, i32)> {
+        match self {
+            TransitionTarget::Point { point } => Some(point),
+            _ => None,
+        }
+    }
+
     /// Performs interpolation, after easing function
     fn interpolate(t: f32, start: &Self, end: &Self) -> Self {
         match (start, end) {
@@ -186,9 +369,28 @@ impl TransitionTarget {
                     (start_point.1 as f32 * (1f32 - t) + end_point.1 as f32 * t) as i32,
                 ),
             },
-            _ => {
-                panic!("Error: 'start' and 'end' parameters must be the same TransitionTarget type!")
+            // ruled out by Transition::initialize, keep the start state rather than crashing mid-animation
+            _ => start.clone(),
+        }
+    }
+
+    /// Checks that start and end can be interpolated between
+    fn check_compatible(start: &Self, end: &Self) -> Result<(), String> {
+        match (start, end) {
+            (
+                TransitionTarget::Points { points: start_points },
+                TransitionTarget::Points { points: end_points },
+            ) => {
+                if start_points.len() != end_points.len() {
+                    return Err(format!(
+                        "Error: 'start' and 'end' points must have the same length ({} != {}) !",
+                        start_points.len(), end_points.len()
+                    ));
+                }
+                Ok(())
             }
+            (TransitionTarget::Point { .. }, TransitionTarget::Point { .. }) => Ok(()),
+            _ => Err("Error: 'start' and 'end' parameters must be the same TransitionTarget type !".to_string()),
         }
     }
 }
@@ -196,6 +398,7 @@ impl TransitionTarget {
 pub struct Transition {
     duration: f32,
     elapsed: f32,
+    rate: f32,
     easing: EasingType,
     start_state: TransitionTarget,
     end_state: TransitionTarget,
@@ -203,27 +406,30 @@ pub struct Transition {
 }
 
 impl Transition {
-    /// Creates a transition
+    /// Creates a transition, fails if start and end are different target types or point counts
     pub fn initialize(
         easing: EasingType,
         duration: f32,
         start_state: TransitionTarget,
         end_state: TransitionTarget,
-    ) -> Self {
+    ) -> Result<Self, String> {
+        TransitionTarget::check_compatible(&start_state, &end_state)?;
         let current_state = start_state.clone();
-        Transition {
+        Ok(Transition {
             easing,
             duration,
             elapsed: 0.0,
+            rate: 1.0,
             start_state,
             end_state,
             current_state,
-        }
+        })
     }
 
     /// Main access point for the Transition : updates the progress based on delta time
     pub fn step(&mut self, delta_time: f32) {
-        self.elapsed += delta_time;
+        // elapsed stays within the timeline so that a negative rate rewinds from the end right away
+        self.elapsed = (self.elapsed + delta_time * self.rate).clamp(0.0, self.duration.max(0.0));
         let t = (self.elapsed / self.duration).clamp(0.0, 1.0);
         let eased_t = self.easing.ease(t);
         self.current_state =
@@ -240,699 +446,2780 @@ impl Transition {
         self.elapsed = 0.0;
     }
 
-    /// Resets Transition and changes start and end targets
-    pub fn reset_new(&mut self, new_start: TransitionTarget, new_end: TransitionTarget) {
+    /// Sets the playback rate applied to delta time in Transition::step (1 by default),
+    /// 0 pauses, 2 plays twice as fast and negative values play backwards
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    /// Swaps start and end targets and resets elapsed time, so that the transition plays backwards
+    /// with the same easing
+    pub fn reverse(&mut self) {
+        std::mem::swap(&mut self.start_state, &mut self.end_state);
+        self.reset();
+        self.current_state = self.start_state.clone();
+    }
+
+    /// Resets Transition and changes start and end targets, fails (leaving the transition unchanged)
+    /// if they are different target types or point counts
+    pub fn reset_new(&mut self, new_start: TransitionTarget, new_end: TransitionTarget) -> Result<(), String> {
+        TransitionTarget::check_compatible(&new_start, &new_end)?;
         self.reset();
 
         self.start_state = new_start;
         self.end_state = new_end;
+        self.current_state = self.start_state.clone();
+        Ok(())
     }
 
     /// Returns the current state if target is points vec
     pub fn get_current_points(&self) -> &Vec<(i32, i32)> {
-        match &self.current_state {
-            TransitionTarget::Points { points } => { points }
-            _ => {panic!("Error: called get_points() on transition with non-points target !")}
-        }
+        self.try_get_current_points()
+            .unwrap_or_else(|| panic!("Error: called get_points() on transition with non-points target !"))
     }
 
     /// Returns the start state if target is points vec
     pub fn get_start_points(&self) -> &Vec<(i32, i32)> {
-        match &self.start_state {
-            TransitionTarget::Points { points } => { points }
-            _ => {panic!("Error: called get_points() on transition with non-points target !")}
-        }
+        self.try_get_start_points()
+            .unwrap_or_else(|| panic!("Error: called get_points() on transition with non-points target !"))
     }
 
     /// Returns the end state if target is points vec
     pub fn get_end_points(&self) -> &Vec<(i32, i32)> {
-        match &self.end_state {
-            TransitionTarget::Points { points } => { points }
-            _ => {panic!("Error: called get_points() on transition with non-points target !")}
-        }
+        self.try_get_end_points()
+            .unwrap_or_else(|| panic!("Error: called get_points() on transition with non-points target !"))
     }
 
     /// Returns current state if target is single point
     pub fn get_current_point(&self) -> &(i32, i32) {
-        match &self.current_state {
-            TransitionTarget::Point { point } => { point }
-            _ => {panic!("Error: called get_point() on transition with non-point target !")}
-        }
+        self.try_get_current_point()
+            .unwrap_or_else(|| panic!("Error: called get_point() on transition with non-point target !"))
     }
-}
 
+    /// Returns the current state if target is points vec, None otherwise
+    pub fn try_get_current_points(&self) -> Option<&Vec<(i32, i32)>> {
+        self.current_state.points()
+    }
 
+    /// Returns the start state if target is points vec, None otherwise
+    pub fn try_get_start_points(&self) -> Option<&Vec<(i32, i32)>> {
+        self.start_state.points()
+    }
 
-pub struct Geometry {}
+    /// Returns the end state if target is points vec, None otherwise
+    pub fn try_get_end_points(&self) -> Option<&Vec<(i32, i32)>> {
+        self.end_state.points()
+    }
 
-impl Geometry {
-    /// Creates a random f32 value between lower and upper bounds
-    pub fn random(lower: f32, upper: f32) -> f32 {
-        lower + rand::random::<f32>() * (upper - lower)
+    /// Returns the current state if target is single point, None otherwise
+    pub fn try_get_current_point(&self) -> Option<&(i32, i32)> {
+        self.current_state.point()
+    }
+
+    /// Returns the start state if target is single point, None otherwise
+    pub fn try_get_start_point(&self) -> Option<&(i32, i32)> {
+        self.start_state.point()
+    }
+
+    /// Returns the end state if target is single point, None otherwise
+    pub fn try_get_end_point(&self) -> Option<&(i32, i32)> {
+        self.end_state.point()
     }
 }
 
-pub trait State : Default {}
+/// Several transitions played on the same timeline
+#[derive(Default)]
+pub struct TransitionGroup {
+    transitions: Vec<Transition>,
+}
 
-pub struct Sketch<S: State> {
-    window: minifb::Window,
-    pixels: Vec<u32>,
+impl TransitionGroup {
+    /// Creates an empty group
+    pub fn new() -> Self {
+        TransitionGroup {transitions: Vec::new()}
+    }
 
-    pub width: usize,
-    pub height: usize,
+    /// Adds a transition to the group and returns its index, used by TransitionGroup::get
+    pub fn add(&mut self, transition: Transition) -> usize {
+        self.transitions.push(transition);
+        self.transitions.len() - 1
+    }
 
-    pub is_looping: bool,
-    pub frame_count: u32,
-    pub delta_time: f32,
+    /// Steps every transition of the group by delta time
+    pub fn step(&mut self, delta_time: f32) {
+        for transition in self.transitions.iter_mut() {
+            transition.step(delta_time);
+        }
+    }
 
-    pub mouse_x: f32,
-    pub mouse_y: f32,
-    pub mouse_is_pressed: bool,
-    pub mouse_button: MouseButton,
+    /// Returns true once every transition of the group is finished
+    pub fn is_finished(&self) -> bool {
+        self.transitions.iter().all(|transition| transition.is_finished())
+    }
 
-    fill_color: Option<u32>,
-    stroke_color: Option<u32>,
-    stroke_weight: i8,
-    stroke_mode: StrokeMode,
+    /// Resets every transition of the group
+    pub fn reset(&mut self) {
+        for transition in self.transitions.iter_mut() {
+            transition.reset();
+        }
+    }
 
-    shape_vertices: Vec<(i32, i32)>,
-    shape_holes: Vec<usize>,
-    shape_type: ShapeType,
+    /// Returns the transition at index, None if out of range
+    pub fn get(&self, index: usize) -> Option<&Transition> {
+        self.transitions.get(index)
+    }
 
-    pub draw_method: Option<fn(&mut Self)>,
-    pub setup_method: Option<fn(&mut Self)>,
-    pub mouse_pressed_method: Option<fn(&mut Self)>,
-    pub mouse_released_method: Option<fn(&mut Self)>,
-    pub key_pressed_method: Option<fn(&mut Self, Key)>,
-    pub key_released_method: Option<fn(&mut Self, Key)>,
+    /// Returns the transition at index for modification, None if out of range
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Transition> {
+        self.transitions.get_mut(index)
+    }
 
-    loaded_fonts: Vec<(Font, String)>,
-    font_index: usize,
+    /// Returns the number of transitions in the group
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
 
-    pub state: S,
+    /// Returns true if the group holds no transition
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
 }
 
-#[allow(dead_code)]
-impl<S: State> Sketch<S> {
-    /// Initializes a Sketch
-    pub fn from_size(width: usize, height: usize, state: S) -> Sketch<S> {
-        let window = minifb::Window::new(DEFAULT_NAME, width, height, minifb::WindowOptions::default())
-            .unwrap_or_else(|e| {
-                panic!("Unable to open window: {}", e);
-            });
+/// Damped spring pulling a value towards a target, unlike a Transition the target can move at any time
+pub struct Spring {
+    position: f32,
+    velocity: f32,
+    target: f32,
+    stiffness: f32,
+    damping: f32,
+}
 
-        let pixels: Vec<u32> = vec![0u32; width*height];
+impl Spring {
+    /// Creates a spring at rest at position, stiffness pulls towards the target and damping slows the motion
+    /// (damping below 2 * sqrt(stiffness) overshoots the target before settling)
+    pub fn new(position: f32, stiffness: f32, damping: f32) -> Self {
+        Spring {
+            position,
+            velocity: 0.0,
+            target: position,
+            stiffness,
+            damping,
+        }
+    }
 
-        let mut sketch = Sketch {
-            window,
-            pixels,
-            width,
-            height,
-            is_looping: true,
-            frame_count: 0,
-            delta_time: 0.0,
-            mouse_x: 0.0,
-            mouse_y: 0.0,
-            mouse_is_pressed: false,
-            mouse_button: MouseButton::Left,
-            fill_color: Some(0),
-            stroke_color: Some(0),
-            stroke_weight: 1,
-            stroke_mode: StrokeMode::Circle,
-            shape_vertices: Vec::new(),
-            shape_holes: Vec::new(),
-            shape_type: ShapeType::Polygon,
+    /// Main access point for the Spring : integrates the motion over delta time
+    pub fn step(&mut self, delta_time: f32) {
+        // small sub steps keep the semi-implicit Euler integration stable for stiff springs and long frames
+        let sub_steps = (delta_time / (1f32 / 240f32)).ceil().max(1f32) as usize;
+        let dt = delta_time / sub_steps as f32;
+
+        for _ in 0..sub_steps {
+            let acceleration = self.stiffness * (self.target - self.position) - self.damping * self.velocity;
+            self.velocity += acceleration * dt;
+            self.position += self.velocity * dt;
+        }
+    }
 
-            draw_method: None,
-            setup_method: None,
-            mouse_pressed_method: None,
-            mouse_released_method: None,
-            key_pressed_method: None,
-            key_released_method: None,
+    /// Changes the target without resetting position or velocity, so the spring chases moving goals
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
 
-            loaded_fonts: Vec::new(),
-            font_index: 0,
+    /// Returns the current position
+    pub fn get_position(&self) -> f32 {
+        self.position
+    }
 
-            state,
-        };
+    /// Returns the current velocity
+    pub fn get_velocity(&self) -> f32 {
+        self.velocity
+    }
 
-        { // Loads default fonts (TimesNewRoman and Arial)
-            let tnr_file_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts/Times New Roman.ttf");
-            let tnr_file_path_str = tnr_file_path.to_str().unwrap();
-            let times_new_roman = sketch.open_ttf_file(tnr_file_path_str);
+    /// Returns the current target
+    pub fn get_target(&self) -> f32 {
+        self.target
+    }
+
+    /// Returns true if the spring is within epsilon of its target and almost not moving
+    pub fn is_at_rest(&self, epsilon: f32) -> bool {
+        (self.target - self.position).abs() <= epsilon && self.velocity.abs() <= epsilon
+    }
+}
 
-            let arial_file_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts/Arial.ttf");
-            let arial_file_path_str = arial_file_path.to_str().unwrap();
-            let arial = sketch.open_ttf_file(arial_file_path_str);
 
-            sketch.loaded_fonts.push((times_new_roman, tnr_file_path_str.to_string()));
-            sketch.loaded_fonts.push((arial, arial_file_path_str.to_string()));
-        }
 
-        sketch
+pub struct Geometry {}
+
+impl Geometry {
+    /// Creates a random f32 value between lower and upper bounds
+    pub fn random(lower: f32, upper: f32) -> f32 {
+        lower + rand::random::<f32>() * (upper - lower)
     }
 
-    // Private Methods
+    /// Creates a random i32 value between lower and upper bounds (both included)
+    pub fn random_int(lower: i32, upper: i32) -> i32 {
+        rand::random_range(lower.min(upper)..=lower.max(upper))
+    }
 
-    /// INTERNAL : interface between Sketch and minifb for mouse interactions
-    fn handle_mouse(&mut self) {
-        (self.mouse_x, self.mouse_y) = self.window.get_mouse_pos(MouseMode::Clamp).unwrap();
+    /// Picks a random element of items, None if items is empty
+    pub fn random_choice<T>(items: &[T]) -> Option<&T> {
+        if items.is_empty() {return None;}
+        items.get(rand::random_range(0..items.len()))
+    }
 
-        let temp = self.mouse_is_pressed;
-        if self.window.get_mouse_down(MouseButton::Left) {
-            self.mouse_is_pressed = true;
-            self.mouse_button = MouseButton::Left;
-        }
-        else if self.window.get_mouse_down(MouseButton::Right) {
-            self.mouse_is_pressed = true;
-            self.mouse_button = MouseButton::Right;
-        }
-        else if self.window.get_mouse_down(MouseButton::Middle) {
-            self.mouse_is_pressed = true;
-            self.mouse_button = MouseButton::Middle;
-        }
-        else {
-            if temp {
-                if let Some(mouse_released_method) = self.mouse_released_method {
-                    mouse_released_method(self);
-                }
-            }
-            self.mouse_is_pressed = false;
-        }
+    /// Creates a normally distributed random f32 value with the given mean and standard deviation
+    /// (Box-Muller transform)
+    pub fn random_gaussian(mean: f32, std_dev: f32) -> f32 {
+        // 1 - u keeps u1 in (0, 1] so that the logarithm stays finite
+        let u1 = 1f32 - rand::random::<f32>();
+        let u2 = rand::random::<f32>();
+        let z = (-2f32 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+        mean + z * std_dev
+    }
 
-        if !temp && self.mouse_is_pressed {
-            if let Some(mouse_pressed_method) = self.mouse_pressed_method {
-                mouse_pressed_method(self);
-            }
-        }
+    /// Linearly remaps value from the [in_min, in_max] range to the [out_min, out_max] range
+    /// (values outside the input range are extrapolated, see Geometry::map_clamped)
+    pub fn map(value: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
+        out_min + (value - in_min) / (in_max - in_min) * (out_max - out_min)
     }
 
-    /// INTERNAL : interface between Sketch and minifb for keyboard interactions
-    fn handle_keys(&mut self) {
-        let keys_pressed:Vec<Key> = self.window.get_keys_pressed(KeyRepeat::No);
+    /// Linearly remaps value like Geometry::map, clamping the result to the output range
+    pub fn map_clamped(value: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
+        let mapped = Geometry::map(value, in_min, in_max, out_min, out_max);
+        mapped.clamp(out_min.min(out_max), out_min.max(out_max))
+    }
 
-        for key in keys_pressed {
-            if let Some(key_pressed_method) = self.key_pressed_method {
-                key_pressed_method(self, key);
-            }
-        }
+    /// Restricts value to the range lower..=upper
+    pub fn constrain(value: f32, lower: f32, upper: f32) -> f32 {
+        value.max(lower).min(upper)
+    }
 
-        let keys_released:Vec<Key> = self.window.get_keys_released();
+    /// Linearly interpolates between a and b, t = 0 gives a and t = 1 gives b
+    pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
 
-        for key in keys_released {
-            if let Some(key_released_method) = self.key_released_method {
-                key_released_method(self, key);
-            }
-        }
+    /// Computes the euclidean distance between x0,y0 and x1,y1
+    pub fn dist(x0: f32, y0: f32, x1: f32, y1: f32) -> f32 {
+        Geometry::dist_sq(x0, y0, x1, y1).sqrt()
     }
 
-    /// main loop of the Sketch
-    pub fn run(&mut self) {
-        self.setup_method.expect("Setup method was not set !")(self);
+    /// Computes the squared euclidean distance between x0,y0 and x1,y1 (cheaper than Geometry::dist)
+    pub fn dist_sq(x0: f32, y0: f32, x1: f32, y1: f32) -> f32 {
+        (x1 - x0) * (x1 - x0) + (y1 - y0) * (y1 - y0)
+    }
 
-        let mut now = std::time::SystemTime::now();
+    /// Converts polar coordinates to cartesian x,y. theta is in radians measured from the positive x axis,
+    /// increasing clockwise on screen since y points down
+    pub fn polar_to_cartesian(r: f32, theta: f32) -> (f32, f32) {
+        (r * theta.cos(), r * theta.sin())
+    }
 
-        while self.window.is_open() {
-            if self.is_looping {
-                self.delta_time = now.elapsed().unwrap().as_secs_f32();
-                now = std::time::SystemTime::now();
+    /// Converts cartesian x,y to polar coordinates (r, theta), theta is in radians within -pi..=pi
+    /// with the same convention as Geometry::polar_to_cartesian
+    pub fn cartesian_to_polar(x: f32, y: f32) -> (f32, f32) {
+        ((x * x + y * y).sqrt(), y.atan2(x))
+    }
 
-                self.handle_mouse();
-                self.handle_keys();
+    /// Rotates point around pivot by angle (radians, clockwise on screen since y points down)
+    pub fn rotate_point(point: (f32, f32), pivot: (f32, f32), angle: f32) -> (f32, f32) {
+        let (sin, cos) = angle.sin_cos();
+        let (dx, dy) = (point.0 - pivot.0, point.1 - pivot.1);
+        (pivot.0 + dx * cos - dy * sin, pivot.1 + dx * sin + dy * cos)
+    }
 
-                self.draw_method.expect("Draw method was not set !")(self);
-            }
+    /// Computes the point at parameter t (0 at p0, 1 at p3) of the cubic Bezier curve with control points p1 and p2,
+    /// using de Casteljau's algorithm
+    pub fn cubic_bezier_point(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p3: (f32, f32), t: f32) -> (f32, f32) {
+        let lerp = |a: (f32, f32), b: (f32, f32)| (Geometry::lerp(a.0, b.0, t), Geometry::lerp(a.1, b.1, t));
 
-            self.window.update_with_buffer(&self.pixels, self.width, self.height).unwrap();
+        let (a, b, c) = (lerp(p0, p1), lerp(p1, p2), lerp(p2, p3));
+        let (d, e) = (lerp(a, b), lerp(b, c));
+        lerp(d, e)
+    }
 
-            if self.is_looping {
-                self.frame_count = self.frame_count + 1;
-            }
-        }
+    /// Computes the point at parameter t (0 at p0, 1 at p2) of the quadratic Bezier curve with control point p1
+    pub fn quadratic_bezier_point(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), t: f32) -> (f32, f32) {
+        let (a, b, c) = ((1f32 - t) * (1f32 - t), 2f32 * (1f32 - t) * t, t * t);
+        (a * p0.0 + b * p1.0 + c * p2.0, a * p0.1 + b * p1.1 + c * p2.1)
     }
 
-    /// Loads a Font from a ttf file path
-    fn open_ttf_file(&self, file_path: &str) -> Font {
-        let mut file_content = Vec::new();
-        fs::File::open(file_path).unwrap().read_to_end(&mut file_content).unwrap();
+    /// Looks up the permutation table, wrapping the index
+    fn permutation(i: i32) -> i32 {
+        PERLIN_PERMUTATION[(i & 255) as usize] as i32
+    }
 
-        Font::from_bytes(file_content, FontSettings::default()).unwrap()
+    /// Perlin fade curve 6t^5 - 15t^4 + 10t^3
+    fn fade(t: f32) -> f32 {
+        t * t * t * (t * (t * 6f32 - 15f32) + 10f32)
     }
 
-    /// Generates a mask based on current stroke mode
-    fn generate_mask(&self) -> Vec<(i8, i8)> {
-        match self.stroke_mode {
-            StrokeMode::Circle => self.generate_circular_mask(),
-            StrokeMode::Square => self.generate_square_mask(),
-            StrokeMode::Custom(mask_func) => mask_func(self.stroke_weight),
-        }
+    /// Dot product between a pseudo-random 1D gradient and the distance x
+    fn gradient_1d(hash: i32, x: f32) -> f32 {
+        if hash & 1 == 0 {x} else {-x}
     }
 
-    /// Pastes mask on Sketch at certain x,y coordinates
-    fn apply_mask_as_stroke(&mut self, x: i32, y: i32, mask: &Vec<(i8, i8)>) {
-        for (i, j) in mask {
-            let (xi, yj) = (x + *i as i32, y + *j as i32);
-            self.stroke_pixel(xi, yj);
+    /// Dot product between a pseudo-random 2D gradient and the distance vector x,y
+    fn gradient_2d(hash: i32, x: f32, y: f32) -> f32 {
+        match hash & 3 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            _ => -x - y,
         }
     }
 
-    /// Traces a line using the low variant of the Bresenham algorithm
-    fn bresenham_plot_line_low(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
-        let dx = x1 - x0;
-        let mut dy = y1 - y0;
-        let mut yi = 1;
-        if dy < 0 {
-            yi = -1;
-            dy = -dy;
-        }
-        let mut delta = 2 * dy - dx;
-        let mut y = y0;
+    /// Linear interpolation used by noise functions
+    fn noise_lerp(a: f32, b: f32, t: f32) -> f32 {
+        a + t * (b - a)
+    }
 
-        let mut result: Vec<(i32, i32)> = vec![];
+    /// Smooth 1D Perlin noise, returns a value between 0 and 1
+    pub fn noise_1d(x: f32) -> f32 {
+        let xi = x.floor() as i32;
+        let xf = x - x.floor();
 
-        for x in x0..=x1 {
-            result.push((x, y));
-            if delta > 0 {
-                y += yi;
-                delta += 2 * (dy - dx);
-            }
-            else {
-                delta += 2 * dy;
-            }
-        }
-        result
+        let u = Geometry::fade(xf);
+        let a = Geometry::gradient_1d(Geometry::permutation(xi), xf);
+        let b = Geometry::gradient_1d(Geometry::permutation(xi + 1), xf - 1f32);
+
+        // 1D gradient noise lies within [-0.5, 0.5]
+        (Geometry::noise_lerp(a, b, u) + 0.5).clamp(0.0, 1.0)
     }
 
-    /// Traces a line using the high variant of the Bresenham algorithm
-    fn bresenham_plot_line_high(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
-        let mut dx = x1 - x0;
-        let dy = y1 - y0;
-        let mut xi = 1;
-        if dx < 0 {
-            xi = -1;
-            dx = -dx;
-        }
-        let mut delta = 2 * dx - dy;
-        let mut x = x0;
+    /// Smooth 2D Perlin noise, returns a value between 0 and 1
+    pub fn noise_2d(x: f32, y: f32) -> f32 {
+        let (xi, yi) = (x.floor() as i32, y.floor() as i32);
+        let (xf, yf) = (x - x.floor(), y - y.floor());
 
-        let mut result: Vec<(i32, i32)> = vec![];
+        let (u, v) = (Geometry::fade(xf), Geometry::fade(yf));
 
-        for y in y0..=y1 {
-            result.push((x, y));
-            if delta > 0 {
-                x += xi;
-                delta += 2 * (dx - dy);
-            }
-            else {
-                delta += 2 * dx;
-            }
-        }
-        result
+        let aa = Geometry::permutation(Geometry::permutation(xi) + yi);
+        let ab = Geometry::permutation(Geometry::permutation(xi) + yi + 1);
+        let ba = Geometry::permutation(Geometry::permutation(xi + 1) + yi);
+        let bb = Geometry::permutation(Geometry::permutation(xi + 1) + yi + 1);
+
+        let bottom = Geometry::noise_lerp(
+            Geometry::gradient_2d(aa, xf, yf),
+            Geometry::gradient_2d(ba, xf - 1f32, yf),
+            u,
+        );
+        let top = Geometry::noise_lerp(
+            Geometry::gradient_2d(ab, xf, yf - 1f32),
+            Geometry::gradient_2d(bb, xf - 1f32, yf - 1f32),
+            u,
+        );
+
+        // 2D gradient noise with these gradients lies within [-1, 1]
+        ((Geometry::noise_lerp(bottom, top, v) + 1f32) / 2f32).clamp(0.0, 1.0)
     }
 
-    /// Traces a line integrating both low and high variants of the Bresenham algorithm
-    fn bresenham_plot_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
-        let points_to_plot: Vec<(i32, i32)>;
+    /// Tests if x,y lies inside a polygon using ray casting, holes are given as start indices of
+    /// hole rings within vertices (like Sketch::begin_hole), points inside holes count as outside.
+    /// Points exactly on an edge are inside for left and top edges and outside for right and bottom
+    /// edges, so two polygons sharing an edge never both contain a point of that edge.
+    pub fn point_in_polygon(vertices: &[(i32, i32)], holes: &[usize], x: f32, y: f32) -> bool {
+        let mut inside = false;
 
-        if (y1 - y0).abs() < (x1 - x0).abs() {
-            if x0 > x1 {
-                points_to_plot = self.bresenham_plot_line_low(x1, y1, x0, y0);
-            }
-            else {
-                points_to_plot = self.bresenham_plot_line_low(x0, y0, x1, y1);
-            }
-        }
-        else {
-            if y0 > y1 {
-                points_to_plot = self.bresenham_plot_line_high(x1, y1, x0, y0);
-            }
-            else {
-                points_to_plot = self.bresenham_plot_line_high(x0, y0, x1, y1);
+        let mut ring_starts: Vec<usize> = vec![0];
+        ring_starts.extend_from_slice(holes);
+        ring_starts.push(vertices.len());
+
+        for ring in ring_starts.windows(2) {
+            let (start, end) = (ring[0], ring[1]);
+            if end <= start {continue;}
+
+            let mut j = end - 1;
+            for i in start..end {
+                let (xi, yi) = (vertices[i].0 as f32, vertices[i].1 as f32);
+                let (xj, yj) = (vertices[j].0 as f32, vertices[j].1 as f32);
+
+                if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                    inside = !inside;
+                }
+                j = i;
             }
         }
-        points_to_plot
+        inside
     }
+}
 
-    /// Applies mask along traced line
-    fn bresenham_plot_line_mask(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, mask: Vec<(i8, i8)>) {
-        let points_to_plot = self.bresenham_plot_line(x0, y0, x1, y1);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
 
-        for point in points_to_plot {
-            self.apply_mask_as_stroke(point.0, point.1, &mask);
-        }
+impl Vec2 {
+    /// Creates a vector from its x,y components
+    pub fn new(x: f32, y: f32) -> Self {
+        Vec2 { x, y }
     }
 
-    /// Plots a point on all octants of a space around xc,yc origin
-    fn plot_on_all_octants(points: &mut Vec<(i32, i32)>, xc: i32, yc: i32, x: i32, y: i32) {
-        points.push((xc + x, yc + y));
-        points.push((xc + y, yc + x));
-
-        points.push((xc - x, yc + y));
-        points.push((xc - y, yc + x));
+    /// Returns the sum of two vectors
+    pub fn add(&self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
 
-        points.push((xc + x, yc - y));
-        points.push((xc + y, yc - x));
+    /// Returns the difference of two vectors
+    pub fn sub(&self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
 
-        points.push((xc - x, yc - y));
-        points.push((xc - y, yc - x));
+    /// Returns the vector multiplied by a scalar
+    pub fn scale(&self, factor: f32) -> Vec2 {
+        Vec2::new(self.x * factor, self.y * factor)
     }
 
-    /// Traces a circle using the Bresenham algorithm (Midpoint algorithm)
-    fn bresenham_plot_circle(&mut self, xc: i32, yc: i32, r: i32) -> Vec<(i32, i32)> {
-        let mut points: Vec<(i32, i32)> = vec![];
+    /// Returns the dot product of two vectors
+    pub fn dot(&self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
 
-        let (mut x, mut y) = (0, r);
-        let mut d = 3 - 2 * r;
+    /// Returns the euclidean length of the vector
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
 
-        while y >= x {
-            Self::plot_on_all_octants(&mut points, xc, yc, x, y);
-            if d > 0 {
-                y -= 1;
-                d += 4 * (x - y) + 10;
-            }
-            else {
-                d += 4 * x + 6;
-            }
-            x += 1;
+    /// Returns the vector scaled to length 1 (the zero vector is returned unchanged)
+    pub fn normalize(&self) -> Vec2 {
+        let length = self.length();
+        if length == 0f32 {
+            return *self;
         }
-        points
+        self.scale(1f32 / length)
     }
 
-    /// Applies stroke mask along traced circle
-    fn circle_stroke(&mut self, xc: i32, yc: i32, r: i32) {
-        let mask = self.generate_mask();
-        let circle = self.bresenham_plot_circle(xc, yc, r);
-        for (x, y) in circle {
-            self.apply_mask_as_stroke(x, y, &mask)
-        }
+    /// Returns the vector rotated by angle (radians, clockwise on screen since y points down)
+    pub fn rotate(&self, angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
     }
 
-    /// Fills a circular region of the sketch using a brute-force algorithm
-    fn circle_fill(&mut self, xc: i32, yc: i32, r: i32) {
-        for xi in -r..=r {
-            for yi in -r..=r {
-                if xi*xi + yi*yi > r*r {continue;}
-                let (x, y) = (xc + xi, yc + yi);
+    /// Linearly interpolates between two vectors, t = 0 gives self and t = 1 gives other
+    pub fn lerp(&self, other: Vec2, t: f32) -> Vec2 {
+        Vec2::new(self.x + (other.x - self.x) * t, self.y + (other.y - self.y) * t)
+    }
+}
 
-                self.fill_pixel(x, y);
-            }
+impl From<(i32, i32)> for Vec2 {
+    fn from(point: (i32, i32)) -> Self {
+        Vec2::new(point.0 as f32, point.1 as f32)
+    }
+}
+
+impl From<Vec2> for (i32, i32) {
+    /// Rounds the vector components to the nearest pixel
+    fn from(vector: Vec2) -> Self {
+        (vector.x.round() as i32, vector.y.round() as i32)
+    }
+}
+
+/// Non-horizontal polygon edge used by the scanline polygon filler
+struct ScanlineEdge {
+    y_top: f32,
+    y_bottom: f32,
+    x_top: f32,
+    slope: f32,
+    winding: i32,
+}
+
+/// Computes the horizontal spans covered by a polygon (holes are start indices of hole rings) using
+/// an active edge table and the given fill rule, a pixel is covered if its center lies inside the polygon.
+/// Calls span(y, x_start, x_end) for each inclusive span, rows are limited to y_min..y_max
+fn polygon_scanline_spans(vertices: &[(i32, i32)], holes: &[usize], rule: FillRule, y_min: i32, y_max: i32, mut span: impl FnMut(i32, i32, i32)) {
+    let mut ring_starts: Vec<usize> = vec![0];
+    ring_starts.extend_from_slice(holes);
+    ring_starts.push(vertices.len());
+
+    let mut edges: Vec<ScanlineEdge> = Vec::new();
+    for ring in ring_starts.windows(2) {
+        let (start, end) = (ring[0], ring[1]);
+        if end <= start {continue;}
+
+        for i in start..end {
+            let (a, b) = (vertices[i], vertices[if i + 1 == end {start} else {i + 1}]);
+            if a.1 == b.1 {continue;}
+
+            let (top, bottom) = if a.1 < b.1 {(a, b)} else {(b, a)};
+            edges.push(ScanlineEdge {
+                y_top: top.1 as f32,
+                y_bottom: bottom.1 as f32,
+                x_top: top.0 as f32,
+                slope: (bottom.0 - top.0) as f32 / (bottom.1 - top.1) as f32,
+                winding: if a.1 < b.1 {1} else {-1},
+            });
         }
     }
+    if edges.is_empty() {return;}
 
-    /// Generates a circular mask with radius = stroke_weight
-    fn generate_circular_mask(&self) -> Vec<(i8, i8)> {
-        let mut mask: Vec<(i8, i8)> = Vec::new();
+    edges.sort_by(|a, b| a.y_top.total_cmp(&b.y_top));
 
-        let stroke_weight_sq = self.stroke_weight * self.stroke_weight;
+    let first_row = (edges[0].y_top as i32).max(y_min);
+    let last_row = (edges.iter().map(|e| e.y_bottom as i32).max().unwrap()).min(y_max);
 
-        for x in -self.stroke_weight..=self.stroke_weight {
-            for y in -self.stroke_weight..=self.stroke_weight {
-                if x * x + y * y <= stroke_weight_sq {
-                    mask.push((x, y));
+    let mut next_edge = 0usize;
+    let mut active: Vec<usize> = Vec::new();
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+
+    for y in first_row..last_row {
+        let y_center = y as f32 + 0.5;
+
+        while next_edge < edges.len() && edges[next_edge].y_top <= y_center {
+            active.push(next_edge);
+            next_edge += 1;
+        }
+        active.retain(|&e| edges[e].y_bottom > y_center);
+
+        crossings.clear();
+        for &e in &active {
+            let edge = &edges[e];
+            crossings.push((edge.x_top + (y_center - edge.y_top) * edge.slope, edge.winding));
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // walk the crossings left to right, a span starts when entering the inside and ends when leaving it
+        let mut winding = 0;
+        let mut span_start = 0f32;
+        for &(x, edge_winding) in &crossings {
+            let was_inside = is_inside_winding(winding, rule);
+            winding += edge_winding;
+            let inside = is_inside_winding(winding, rule);
+
+            if !was_inside && inside {
+                span_start = x;
+            }
+            else if was_inside && !inside {
+                let x_start = (span_start - 0.5).ceil() as i32;
+                let x_end = (x - 0.5).ceil() as i32 - 1;
+                if x_end >= x_start {
+                    span(y, x_start, x_end);
                 }
             }
         }
-        mask
     }
+}
+
+/// Tests if a winding number counts as inside under the given fill rule
+fn is_inside_winding(winding: i32, rule: FillRule) -> bool {
+    match rule {
+        FillRule::EvenOdd => winding % 2 != 0,
+        FillRule::NonZero => winding != 0,
+    }
+}
+
+/// Checks that a sketch of width by height can be created
+fn validate_dimensions(width: usize, height: usize) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("Error: sketch dimensions must be non-zero (got {}x{}) !", width, height));
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(format!("Error: sketch dimensions must be at most {} (got {}x{}) !", MAX_DIMENSION, width, height));
+    }
+    Ok(())
+}
+
+/// Clamps the rectangle x,y,w,h (negative lengths extend the other way) to a width by height buffer,
+/// returns the region as (x, y, w, h) or None if nothing is left
+fn clip_region(x: i32, y: i32, w: i32, h: i32, width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+    let (x, y, w, h) = normalize_rect(x, y, w, h);
+    let (x_start, y_start) = (x.max(0), y.max(0));
+    let x_end = x.saturating_add(w).min(width as i32);
+    let y_end = y.saturating_add(h).min(height as i32);
+    if x_end <= x_start || y_end <= y_start {return None;}
+
+    Some((x_start as usize, y_start as usize, (x_end - x_start) as usize, (y_end - y_start) as usize))
+}
 
-    /// Generates a square mask with side_length = 2 * stroke_weight
-    fn generate_square_mask(&self) -> Vec<(i8, i8)> {
-        let v1 = -self.stroke_weight;
-        let v2 = self.stroke_weight;
+/// Intersects the region (x, y, w, h) with clip (if any), returns None if they do not overlap
+fn intersect_region(region: (usize, usize, usize, usize), clip: Option<(usize, usize, usize, usize)>) -> Option<(usize, usize, usize, usize)> {
+    let (x, y, w, h) = region;
+    let Some((cx, cy, cw, ch)) = clip else {return Some(region);};
 
-        let mut mask: Vec<(i8, i8)> = Vec::new();
+    let (x0, y0) = (x.max(cx), y.max(cy));
+    let (x1, y1) = ((x + w).min(cx + cw), (y + h).min(cy + ch));
+    if x1 <= x0 || y1 <= y0 {return None;}
+    Some((x0, y0, x1 - x0, y1 - y0))
+}
 
-        for v in v1..=v2 {
-            mask.push((v1, v));
-            mask.push((v2, v));
+/// Multiplies every channel of color by tint (if any)
+fn tint_color(color: u32, tint: Option<u32>) -> u32 {
+    let Some(tint) = tint else {
+        return color;
+    };
+
+    let multiply = |c: u8, t: u8| ((c as u32 * t as u32 + 127) / 255) as u8;
+    RgbaColor::argb_color(
+        multiply(RgbaColor::color_alpha(color), RgbaColor::color_alpha(tint)),
+        multiply(RgbaColor::color_red(color), RgbaColor::color_red(tint)),
+        multiply(RgbaColor::color_green(color), RgbaColor::color_green(tint)),
+        multiply(RgbaColor::color_blue(color), RgbaColor::color_blue(tint)),
+    )
+}
 
-            mask.push((v, v1));
-            mask.push((v, v2));
+/// Box blurs a line of colors channel by channel with a sliding window of 2 * radius + 1 values,
+/// values past both ends are clamped to the end values
+fn box_blur_line(line: &[u32], radius: usize) -> Vec<u32> {
+    let last = line.len() as i64 - 1;
+    let at = |i: i64| line[i.clamp(0, last) as usize];
+    let window = (2 * radius + 1) as u32;
+
+    let mut sums = [0u32; 4];
+    let add = |sums: &mut [u32; 4], color: u32, sign: bool| {
+        for (channel, sum) in sums.iter_mut().enumerate() {
+            let value = (color >> (channel * 8)) & 255;
+            if sign {*sum += value} else {*sum -= value}
         }
-        mask
+    };
+
+    let radius = radius as i64;
+    for k in -radius..=radius {
+        add(&mut sums, at(k), true);
     }
 
-    /// Fills a flat triangle situated above its base
-    fn triangle_flat_top(&mut self, x0: i32, y0: i32, base: i32, x1: i32, y1: i32) {
-        let left_edge = self.bresenham_plot_line(x0, y0, x1, y1);
-        let right_edge = self.bresenham_plot_line(x0 + base, y0, x1, y1);
+    let mut blurred = Vec::with_capacity(line.len());
+    for i in 0..line.len() as i64 {
+        let color = sums.iter().enumerate().fold(0u32, |color, (channel, sum)| {
+            color | (((sum + window / 2) / window) << (channel * 8))
+        });
+        blurred.push(color);
 
-        let mut max_right_x: Vec<i32> = vec![i32::MIN; (y1 - y0 + 1) as usize];
-        let mut min_left_x: Vec<i32> = vec![i32::MAX; (y1 - y0 + 1) as usize];
+        add(&mut sums, at(i + radius + 1), true);
+        add(&mut sums, at(i - radius), false);
+    }
+    blurred
+}
 
-        for (x, y) in right_edge {
-            let i = (y - y0) as usize;
-            if x > max_right_x[i] {
-                max_right_x[i] = x;
-            }
-        }
-        for (x, y) in left_edge {
-            let i = (y - y0) as usize;
-            if x < min_left_x[i] {
-                min_left_x[i] = x;
-            }
+/// Blurs the region (x, y, w, h) of a row-major pixel buffer with a horizontal then a vertical box blur pass,
+/// only pixels within the region are sampled, the radius of each pass is clamped to the side it runs along
+fn box_blur_region(pixels: &mut [u32], width: usize, region: (usize, usize, usize, usize), radius: usize) {
+    let (x, y, w, h) = region;
+    // keeps the window sums from overflowing, a wider window would only weigh the clamped ends more
+    let (radius_x, radius_y) = (radius.min(w), radius.min(h));
+
+    for j in y..y + h {
+        let row = &mut pixels[x + j * width..x + w + j * width];
+        let blurred = box_blur_line(row, radius_x);
+        row.copy_from_slice(&blurred);
+    }
+
+    for i in x..x + w {
+        let column: Vec<u32> = (y..y + h).map(|j| pixels[i + j * width]).collect();
+        for (j, color) in (y..y + h).zip(box_blur_line(&column, radius_y)) {
+            pixels[i + j * width] = color;
         }
+    }
+}
 
-        for i in 0..max_right_x.len() {
-            let y = i as i32 + y0;
-            for x in min_left_x[i]..=max_right_x[i] {
-                self.change_pixel(x, y, self.fill_color.unwrap());
+/// Applies a kernel_size x kernel_size kernel (row-major) to the rgb channels of the pixels of source within region,
+/// writing the results into target (alpha is kept), samples outside the buffer are clamped to its edges
+fn convolve_region(source: &[u32], target: &mut [u32], width: usize, height: usize, region: (usize, usize, usize, usize), kernel: &[f32], kernel_size: usize) {
+    let x = region.0;
+    let half = (kernel_size / 2) as i64;
+
+    for_each_row_span(target, width, region, |j, row| {
+        for (offset, pixel) in row.iter_mut().enumerate() {
+            let i = x + offset;
+            let mut sums = [0f32; 3];
+            for (k, weight) in kernel.iter().enumerate() {
+                let sx = (i as i64 + (k % kernel_size) as i64 - half).clamp(0, width as i64 - 1) as usize;
+                let sy = (j as i64 + (k / kernel_size) as i64 - half).clamp(0, height as i64 - 1) as usize;
+                let color = source[sx + sy * width];
+
+                sums[0] += RgbaColor::color_red(color) as f32 * weight;
+                sums[1] += RgbaColor::color_green(color) as f32 * weight;
+                sums[2] += RgbaColor::color_blue(color) as f32 * weight;
             }
+
+            let channel = |sum: f32| sum.round().clamp(0.0, 255.0) as u8;
+            let alpha = RgbaColor::color_alpha(source[i + j * width]);
+            *pixel = RgbaColor::argb_color(alpha, channel(sums[0]), channel(sums[1]), channel(sums[2]));
         }
+    });
+}
+
+/// Calls f with the row index and the pixels of every row of region (x, y, w, h) in a row-major buffer,
+/// rows are spread across threads for large regions when the parallel feature is enabled
+fn for_each_row_span<F>(pixels: &mut [u32], width: usize, region: (usize, usize, usize, usize), f: F)
+where
+    F: Fn(usize, &mut [u32]) + Send + Sync,
+{
+    let (x, y, w, h) = region;
+    let rows = &mut pixels[y * width..(y + h) * width];
+
+    #[cfg(feature = "parallel")]
+    if w * h >= PARALLEL_MIN_PIXELS {
+        use rayon::prelude::*;
+        rows.par_chunks_mut(width).enumerate().for_each(|(j, row)| f(y + j, &mut row[x..x + w]));
+        return;
     }
 
-    /// Fills a flat triangle situated below its base
-    fn triangle_flat_bottom(&mut self, x0: i32, y0: i32, base: i32, x1: i32, y1: i32) {
-        let left_edge = self.bresenham_plot_line(x1, y1, x0, y0);
-        let right_edge = self.bresenham_plot_line(x1, y1, x0 + base, y0);
+    rows.chunks_mut(width).enumerate().for_each(|(j, row)| f(y + j, &mut row[x..x + w]));
+}
+
+/// Returns the index of the font loaded from file_path, loading it with open and storing it first if it is not loaded yet
+fn find_or_load_font(loaded_fonts: &mut Vec<(Font, String)>, file_path: &str, open: impl FnOnce(&str) -> Font) -> usize {
+    if let Some(index) = loaded_fonts.iter().position(|(_, fp)| fp == file_path) {
+        return index;
+    }
 
-        let mut max_right_x: Vec<i32> = vec![i32::MIN; (y0 - y1 + 1) as usize];
-        let mut min_left_x: Vec<i32> = vec![i32::MAX; (y0 - y1 + 1) as usize];
+    loaded_fonts.push((open(file_path), file_path.to_string()));
+    loaded_fonts.len() - 1
+}
 
-        for (x, y) in right_edge {
-            let i = (y - y1) as usize;
-            if x > max_right_x[i] {
-                max_right_x[i] = x;
+/// Breaks string into lines at word boundaries so that no line measures more than max_width with measure,
+/// words wider than max_width get a line of their own, explicit newlines are kept
+fn wrap_words(string: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in string.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {word.to_string()} else {format!("{} {}", line, word)};
+            if line.is_empty() || measure(&candidate) <= max_width {
+                line = candidate;
             }
-        }
-        for (x, y) in left_edge {
-            let i = (y - y1) as usize;
-            if x < min_left_x[i] {
-                min_left_x[i] = x;
+            else {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
             }
         }
+        lines.push(line);
+    }
+    lines
+}
 
-        for i in 0..max_right_x.len() {
-            let y = i as i32 + y1;
-            for x in min_left_x[i]..=max_right_x[i] {
-                self.change_pixel(x, y, self.fill_color.unwrap());
+/// Collects the indices of the 4-connected pixels of a row-major buffer that have the same color as the seed x,y,
+/// using an explicit stack so that large regions do not overflow the call stack
+fn flood_fill_region(pixels: &[u32], width: usize, height: usize, x: i32, y: i32) -> Vec<usize> {
+    if x < 0 || y < 0 || x as usize >= width || y as usize >= height {return Vec::new();}
+
+    let target = pixels[x as usize + y as usize * width];
+    let mut visited = vec![false; pixels.len()];
+    let mut region = Vec::new();
+    let mut stack = vec![(x as usize, y as usize)];
+    visited[x as usize + y as usize * width] = true;
+
+    while let Some((x, y)) = stack.pop() {
+        region.push(x + y * width);
+
+        let neighbors = [
+            (x > 0).then(|| (x - 1, y)),
+            (x + 1 < width).then_some((x + 1, y)),
+            (y > 0).then(|| (x, y - 1)),
+            (y + 1 < height).then_some((x, y + 1)),
+        ];
+        for (nx, ny) in neighbors.into_iter().flatten() {
+            let index = nx + ny * width;
+            if !visited[index] && pixels[index] == target {
+                visited[index] = true;
+                stack.push((nx, ny));
             }
         }
     }
+    region
+}
 
-    /// Fills a triangle by separating into top and bottom parts
-    fn triangle_fill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, x2: i32, y2: i32) {
-        let mut triangle = [(x0, y0), (x1, y1), (x2, y2)];
-        triangle.sort_by(|&(_, y1), &(_, y2)| y2.cmp(&y1));
+/// Tests if angle (radians) lies within the range going from start_angle to end_angle in increasing angle
+/// direction, wrapping around 0/2pi. Ranges spanning 2pi or more contain every angle
+fn angle_in_range(angle: f32, start_angle: f32, end_angle: f32) -> bool {
+    use std::f32::consts::TAU;
+    if end_angle - start_angle >= TAU {return true;}
 
-        let x_project = f32::round(
-            (triangle[1].1 - triangle[2].1) as f32
-                * ((triangle[2].0 - triangle[0].0) as f32 / (triangle[2].1 - triangle[0].1) as f32)
-                + triangle[2].0 as f32
-        ) as i32;
+    let span = (end_angle - start_angle).rem_euclid(TAU);
+    (angle - start_angle).rem_euclid(TAU) <= span
+}
 
-        let mut x_start = x_project;
-        let mut base = triangle[1].0 - x_project;
-        if triangle[1].0 < x_project {
-            x_start = triangle[1].0;
-            base = -base;
+/// Recursively splits the cubic Bezier curve p0,p1,p2,p3 in half until it is flat within tolerance pixels
+/// (or 16 levels deep), pushing the rounded end point of every flat piece
+fn cubic_bezier_subdivide(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p3: (f32, f32), tolerance: f32, depth: u32, points: &mut Vec<(i32, i32)>) {
+    let chord = (p3.0 - p0.0, p3.1 - p0.1);
+    let chord_length = (chord.0 * chord.0 + chord.1 * chord.1).sqrt();
+    let distance = |p: (f32, f32)| {
+        if chord_length == 0f32 {Geometry::dist(p.0, p.1, p0.0, p0.1)}
+        else {((p.0 - p0.0) * chord.1 - (p.1 - p0.1) * chord.0).abs() / chord_length}
+    };
+
+    if depth >= 16 || (distance(p1) <= tolerance && distance(p2) <= tolerance) {
+        let end = (p3.0.round() as i32, p3.1.round() as i32);
+        if points.last() != Some(&end) {
+            points.push(end);
         }
-
-        self.triangle_flat_bottom(x_start, triangle[1].1, base, triangle[2].0, triangle[2].1);
-        self.triangle_flat_top(x_start, triangle[1].1, base, triangle[0].0, triangle[0].1);
+        return;
     }
 
-    /// Strokes the 3 sides of a triangle
-    fn triangle_stroke(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, x2: i32, y2: i32) {
-        self.line(x0, y0, x1, y1);
-        self.line(x0, y0, x2, y2);
-        self.line(x2, y2, x1, y1);
-    }
+    let mid = |a: (f32, f32), b: (f32, f32)| ((a.0 + b.0) / 2f32, (a.1 + b.1) / 2f32);
+    let (a, b, c) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let middle = mid(d, e);
 
-    /// General method for changing the color of a pixel (includes bound checks)
-    fn change_pixel(&mut self, x: i32, y: i32, color: u32) {
-        if x < 0 ||y < 0 ||x >= self.width as i32 || y >= self.height as i32 {return;}
+    cubic_bezier_subdivide(p0, a, d, middle, tolerance, depth + 1, points);
+    cubic_bezier_subdivide(middle, e, c, p3, tolerance, depth + 1, points);
+}
 
-        let (x, y) = (x as u32, y as u32);
+/// Clips the segment x0,y0 - x1,y1 to the rectangle x_min,y_min,x_max,y_max with the Cohen-Sutherland algorithm,
+/// returns the clipped endpoints or None if the segment lies outside
+fn cohen_sutherland_clip(
+    (mut x0, mut y0): (f64, f64),
+    (mut x1, mut y1): (f64, f64),
+    (x_min, y_min, x_max, y_max): (f64, f64, f64, f64),
+) -> Option<((f64, f64), (f64, f64))> {
+    const LEFT: u8 = 1;
+    const RIGHT: u8 = 2;
+    const TOP: u8 = 4;
+    const BOTTOM: u8 = 8;
+
+    let outcode = |x: f64, y: f64| {
+        let mut code = 0;
+        if x < x_min {code |= LEFT;} else if x > x_max {code |= RIGHT;}
+        if y < y_min {code |= TOP;} else if y > y_max {code |= BOTTOM;}
+        code
+    };
+
+    let (mut code0, mut code1) = (outcode(x0, y0), outcode(x1, y1));
+    loop {
+        if code0 | code1 == 0 {return Some(((x0, y0), (x1, y1)));}
+        if code0 & code1 != 0 {return None;}
+
+        // moves an outside endpoint onto the edge it lies beyond
+        let code = if code0 != 0 {code0} else {code1};
+        let (x, y) = if code & TOP != 0 {
+            (x0 + (x1 - x0) * (y_min - y0) / (y1 - y0), y_min)
+        } else if code & BOTTOM != 0 {
+            (x0 + (x1 - x0) * (y_max - y0) / (y1 - y0), y_max)
+        } else if code & LEFT != 0 {
+            (x_min, y0 + (y1 - y0) * (x_min - x0) / (x1 - x0))
+        } else {
+            (x_max, y0 + (y1 - y0) * (x_max - x0) / (x1 - x0))
+        };
 
-        if RgbaColor::color_alpha(color) == 255 {
-            self.set_pixel(x, y, color);
-        }
-        else {
-            self.mix_pixel(x, y, color);
+        if code == code0 {
+            (x0, y0) = (x, y);
+            code0 = outcode(x0, y0);
+        } else {
+            (x1, y1) = (x, y);
+            code1 = outcode(x1, y1);
         }
     }
+}
 
-    /// Changes the color of the pixel at x,y
-    fn set_pixel(&mut self, x: u32, y: u32, color: u32) {
-        let index = x as usize + y as usize * self.width;
-        self.pixels[index] = color;
+/// Traces the Bresenham line from x0,y0 to x1,y1, keeping only the steps that may come within margin pixels of
+/// a width by height area, returns the position along the line (counted from x0,y0) of the first kept step and
+/// the kept points in order, those are the same pixels as tracing the whole line
+fn bresenham_line_clipped(x0: i32, y0: i32, x1: i32, y1: i32, width: usize, height: usize, margin: i32) -> (usize, Vec<(i32, i32)>) {
+    // walks along the major axis a, from the endpoint with the smallest a
+    let steep = (y1 as i64 - y0 as i64).abs() >= (x1 as i64 - x0 as i64).abs();
+    let ((a0, b0), (a1, b1), a_size, b_size) = if steep {
+        ((y0, x0), (y1, x1), height, width)
+    } else {
+        ((x0, y0), (x1, y1), width, height)
+    };
+    let reversed = a0 > a1;
+    let ((a0, b0), (a1, b1)) = if reversed {((a1, b1), (a0, b0))} else {((a0, b0), (a1, b1))};
+
+    // one extra pixel as the traced minor coordinate is up to half a pixel away from the exact line
+    let pad = margin.max(0) as f64 + 1f64;
+    let area = (-pad, -pad, a_size as f64 - 1f64 + pad, b_size as f64 - 1f64 + pad);
+    let Some(((ca0, _), (ca1, _))) = cohen_sutherland_clip((a0 as f64, b0 as f64), (a1 as f64, b1 as f64), area) else {
+        return (0, vec![]);
+    };
+
+    let da = a1 as i64 - a0 as i64;
+    let db = (b1 as i64 - b0 as i64).abs();
+    let bi = if b1 < b0 {-1} else {1};
+    let first = (ca0.min(ca1).floor() as i64 - a0 as i64 - 1).max(0);
+    let last = (ca0.max(ca1).ceil() as i64 - a0 as i64 + 1).min(da);
+    if first > last {return (0, vec![]);}
+
+    // minor steps taken before the first kept step, closed form of the Bresenham error accumulation
+    // (in i128 as the products overflow for lines spanning the whole i32 range)
+    let (da_wide, db_wide, first_wide) = (da as i128, db as i128, first as i128);
+    let b_steps_wide = if da == 0 {0} else {(2 * db_wide * first_wide + da_wide - 1).div_euclid(2 * da_wide)};
+    let mut delta = (2 * db_wide * (first_wide + 1) - da_wide - 2 * da_wide * b_steps_wide) as i64;
+    let mut b_steps = b_steps_wide as i64;
+
+    let mut points: Vec<(i32, i32)> = Vec::with_capacity((last - first + 1) as usize);
+    for k in first..=last {
+        let (a, b) = ((a0 as i64 + k) as i32, (b0 as i64 + bi * b_steps) as i32);
+        points.push(if steep {(b, a)} else {(a, b)});
+        if delta > 0 {
+            b_steps += 1;
+            delta += 2 * (db - da);
+        }
+        else {
+            delta += 2 * db;
+        }
     }
 
-    /// Changes the color of a pixel using alpha compose with previous color
-    fn mix_pixel(&mut self, x: u32, y: u32, color: u32) {
-        let index = x as usize + y as usize * self.width;
-        let new_color = RgbaColor::color_alpha_compose_color(self.pixels[index], color);
-        self.pixels[index] = new_color;
+    if reversed {
+        points.reverse();
+        ((da - last) as usize, points)
+    } else {
+        (first as usize, points)
     }
+}
 
-    /// Fills the inside of a rectangle at x,y with side lengths w,h
-    fn rect_fill(&mut self, x: i32, y: i32, w: i32, h: i32) {
-        for i in x..(x+w) {
-            for j in y..(y+h) {
-                self.fill_pixel(i, j);
+/// Averages every factor x factor block of a width by height row-major buffer (all four channels, rounded),
+/// returns the (width / factor) by (height / factor) result
+fn box_downsample(pixels: &[u32], width: usize, height: usize, factor: usize) -> Vec<u32> {
+    let (out_width, out_height) = (width / factor, height / factor);
+    let count = (factor * factor) as u32;
+    let mut result = vec![0u32; out_width * out_height];
+
+    for (index, pixel) in result.iter_mut().enumerate() {
+        let (x, y) = ((index % out_width) * factor, (index / out_width) * factor);
+
+        let mut sums = [0u32; 4];
+        for j in y..y + factor {
+            for color in &pixels[x + j * width..x + factor + j * width] {
+                for (channel, sum) in sums.iter_mut().enumerate() {
+                    *sum += (color >> (channel * 8)) & 255;
+                }
             }
         }
+        *pixel = sums.iter().enumerate().fold(0u32, |color, (channel, sum)| {
+            color | (((sum + count / 2) / count) << (channel * 8))
+        });
     }
+    result
+}
 
-    /// Strokes the 4 sides of a rectangle at x,y with side lengths w,h
-    fn rect_stroke(&mut self, x: i32, y: i32, w: i32, h: i32) {
-        self.line(x, y, x+w, y);
-        self.line(x, y, x, y+h);
-        self.line(x, y+h, x+w, y+h);
-        self.line(x+w, y, x+w, y+h);
+/// Longest miter, in half stroke widths, before a Miter join falls back to a bevel
+const MITER_LIMIT: f32 = 4f32;
+
+/// Corners of the polygon closing the outer side of the joint at v between segments a-v and v-b, for a stroke
+/// extending half_width on each side (v first). None for collinear or degenerate segments and Round joins
+fn join_wedge(a: (f32, f32), v: (f32, f32), b: (f32, f32), half_width: f32, join: StrokeJoin) -> Option<Vec<(f32, f32)>> {
+    let (d1, d2) = (Vec2::new(v.0 - a.0, v.1 - a.1), Vec2::new(b.0 - v.0, b.1 - v.1));
+    if d1.length() == 0f32 || d2.length() == 0f32 || join == StrokeJoin::Round {return None;}
+    let (d1, d2) = (d1.normalize(), d2.normalize());
+
+    let cross = d1.x * d2.y - d1.y * d2.x;
+    if cross.abs() < 1e-6 {return None;}
+
+    // the outer side is the one the path turns away from
+    let side = -cross.signum();
+    let (n1, n2) = (Vec2::new(-d1.y, d1.x).scale(side), Vec2::new(-d2.y, d2.x).scale(side));
+    let center = Vec2::new(v.0, v.1);
+    let (p1, p2) = (center.add(n1.scale(half_width)), center.add(n2.scale(half_width)));
+
+    if join == StrokeJoin::Miter {
+        // the tip lies along the bisector of both normals
+        let bisector = n1.add(n2).normalize();
+        let miter_length = half_width / bisector.dot(n1);
+        if miter_length <= MITER_LIMIT * half_width {
+            let tip = center.add(bisector.scale(miter_length));
+            return Some(vec![v, (p1.x, p1.y), (tip.x, tip.y), (p2.x, p2.y)]);
+        }
     }
+    Some(vec![v, (p1.x, p1.y), (p2.x, p2.y)])
+}
 
-    /// Triangulates and fills current constructed polygon
-    fn polygon_fill(&mut self) {
-        let mut coords: Vec<f64> = Vec::new();
-
-        for point in &self.shape_vertices {
-            coords.push(point.0 as f64);
-            coords.push(point.1 as f64);
-        }
+/// Fraction of a pixel at distance from a circle center covered by an outline of radius r extending half_width on
+/// each side, approximated with a one pixel wide box filter across the edges
+fn circle_outline_coverage(distance: f32, r: f32, half_width: f32) -> f32 {
+    (half_width + 0.5 - (distance - r).abs()).clamp(0f32, 1f32)
+}
 
-        let triangles = earcutr::earcut(&coords, &self.shape_holes, 2).unwrap_or_else(|e| panic!("Triangulation error: {e}"));
+/// Generates a circular mask with radius = weight (a single pixel for weight 0)
+fn circular_mask(weight: i32) -> Vec<(i32, i32)> {
+    let weight = weight.max(0);
+    let mut mask: Vec<(i32, i32)> = Vec::new();
 
-        println!("triangles :");
+    let weight_sq = weight * weight;
 
-        for i in 0..(triangles.len() / 3) {
-            let (a, b, c) = (triangles[3*i], triangles[3*i+1], triangles[3*i+2]);
-            self.triangle_fill(
-                self.shape_vertices[a].0, self.shape_vertices[a].1,
-                self.shape_vertices[b].0, self.shape_vertices[b].1,
-                self.shape_vertices[c].0, self.shape_vertices[c].1
-            )
+    for x in -weight..=weight {
+        for y in -weight..=weight {
+            if x * x + y * y <= weight_sq {
+                mask.push((x, y));
+            }
         }
     }
+    mask
+}
 
-    /// Strokes all edges of the current constructed polygon
-    fn polygon_stroke(&mut self) {
-        self.linear_spline(true);
+/// Generates a square mask with side_length = 2 * weight (a single pixel for weight 0)
+fn square_mask(weight: i32) -> Vec<(i32, i32)> {
+    let weight = weight.max(0);
+    if weight == 0 {
+        return vec![(0, 0)];
     }
+    let v1 = -weight;
+    let v2 = weight;
 
-    /// Draws a polygon based on current shape construction
-    fn polygon(&mut self) {
-        if self.fill_color.is_some() {
-            self.polygon_fill();
-        }
-        if self.stroke_color.is_some() {
-            self.polygon_stroke();
-        }
-    }
+    let mut mask: Vec<(i32, i32)> = Vec::new();
 
-    /// Draws a linear spline based on the current shape construction, holes separate different chains
-    fn linear_spline(&mut self, loops: bool) {
-        let mut start = 0usize;
-        let mut hole = 0usize;
-        for i in 0..self.shape_vertices.len() {
-            if !loops && (i == self.shape_vertices.len() - 1 || (hole < self.shape_holes.len() && i == self.shape_holes[hole] - 1)) {
-                continue;
-            }
+    for v in v1..=v2 {
+        mask.push((v1, v));
+        mask.push((v2, v));
 
-            let (x0, y0) = self.shape_vertices[i];
-            let (x1, y1) = self.shape_vertices[
-                if i == self.shape_vertices.len() - 1 || (hole < self.shape_holes.len() && i == self.shape_holes[hole] - 1) {start}
-                else {i + 1}
-            ];
+        mask.push((v, v1));
+        mask.push((v, v2));
+    }
+    mask
+}
 
-            self.line(x0, y0, x1, y1);
+/// Writes frames (width, height, pixels) to file_path as an infinitely looping gif at fps frames per second,
+/// frames smaller than the largest one are drawn at its top left corner over black
+fn encode_gif(file_path: &str, frames: &[(usize, usize, Vec<u32>)], fps: u32) -> Result<(), String> {
+    let file = fs::File::create(file_path)
+        .map_err(|e| format!("Unable to create recording '{}' : {}", file_path, e))?;
 
-            if hole < self.shape_holes.len() && i == self.shape_holes[hole] {
-                hole += 1;
-                start = i;
-            }
-        }
-    }
+    let mut encoder = GifEncoder::new(file);
+    encoder.set_repeat(Repeat::Infinite)
+        .map_err(|e| format!("Unable to encode recording : {}", e))?;
 
-    // Public Methods
+    let canvas_width = frames.iter().map(|(width, _, _)| *width).max().unwrap_or(0);
+    let canvas_height = frames.iter().map(|(_, height, _)| *height).max().unwrap_or(0);
 
-    /// Changes the name of the window
-    pub fn name(&mut self, name: &str) {
-        self.window.set_title(name);
-    }
+    for (width, height, pixels) in frames {
+        let image = ImageBuffer::from_fn(canvas_width as u32, canvas_height as u32, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            let pixel = if x < *width && y < *height {pixels[x + y * width]} else {0};
+            Rgba([RgbaColor::color_red(pixel), RgbaColor::color_green(pixel), RgbaColor::color_blue(pixel), 255])
+        });
 
-    /// Checks if the key: Key is currently pressed
-    pub fn key_is_down(&self, key: Key) -> bool{
-        self.window.is_key_down(key)
+        encoder.encode_frame(Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(1000, fps)))
+            .map_err(|e| format!("Unable to encode recording : {}", e))?;
     }
+    Ok(())
+}
 
-    /// Stops the animation loop
-    pub fn no_loop(&mut self) {
-        self.is_looping = false;
+/// Converts the duration of a frame in seconds to a framerate (0 for an empty frame time)
+fn framerate_from_frame_time(frame_time: f32) -> f32 {
+    if frame_time <= 0.0 {
+        return 0.0;
     }
+    1.0 / frame_time
+}
 
-    /// Sets the framerate limit of the window
-    pub fn framerate(&mut self, fps: usize) {
-        self.window.set_target_fps(fps);
-    }
+/// Computes the largest integer whose square is at most value (0 for negative values)
+fn integer_sqrt(value: i64) -> i64 {
+    if value <= 0 {return 0;}
 
-    /// Sets the current fill color
-    pub fn fill(&mut self, color: u32) {
-        self.fill_color = Some(color);
-    }
+    let mut root = (value as f64).sqrt() as i64;
+    while root * root > value {root -= 1;}
+    while (root + 1) * (root + 1) <= value {root += 1;}
+    root
+}
 
-    /// Removes current fill color, drawn shapes will be hollow
-    pub fn no_fill(&mut self) {
-        self.fill_color = None;
-    }
+/// Converts a rectangle with possibly negative side lengths into the same rectangle with positive ones
+fn normalize_rect(x: i32, y: i32, w: i32, h: i32) -> (i32, i32, i32, i32) {
+    let (x, w) = if w < 0 {(x.saturating_add(w), w.saturating_neg())} else {(x, w)};
+    let (y, h) = if h < 0 {(y.saturating_add(h), h.saturating_neg())} else {(y, h)};
+    (x, y, w, h)
+}
 
-    /// Sets the current stroke color
-    pub fn stroke(&mut self, color: u32) {
-        self.stroke_color = Some(color);
-    }
+/// Computes the x coordinate of an edge a,b at height y
+fn edge_x_at(a: (i32, i32), b: (i32, i32), y: f32) -> f32 {
+    a.0 as f32 + (y - a.1 as f32) * (b.0 - a.0) as f32 / (b.1 - a.1) as f32
+}
 
-    /// Removes the current stroke color, drawn shapes will not have an outline
-    pub fn no_stroke(&mut self) {
-        self.stroke_color = None;
+/// Computes the spans of a flat triangle between edge_a and edge_b, over rows y_start..y_end (exclusive end)
+fn triangle_half_spans(edge_a: ((i32, i32), (i32, i32)), edge_b: ((i32, i32), (i32, i32)), y_start: i32, y_end: i32, span: &mut impl FnMut(i32, i32, i32)) {
+    for y in y_start..y_end {
+        let y_center = y as f32 + 0.5;
+        let xa = edge_x_at(edge_a.0, edge_a.1, y_center);
+        let xb = edge_x_at(edge_b.0, edge_b.1, y_center);
+
+        let x_start = (xa.min(xb) - 0.5).ceil() as i32;
+        let x_end = (xa.max(xb) - 0.5).ceil() as i32 - 1;
+        if x_end >= x_start {
+            span(y, x_start, x_end);
+        }
     }
+}
 
-    /// Sets the thickness of the outline
-    pub fn stroke_weight(&mut self, weight: i8) {
-        self.stroke_weight = weight;
-    }
+/// Computes the spans covered by a triangle, a pixel is covered if its center lies inside the triangle.
+/// The triangle is split at its middle vertex into a flat bottom part and a flat top part which never
+/// share a row, and each row is computed from the exact edges so that no row is skipped.
+/// Calls span(y, x_start, x_end) for each inclusive span
+fn triangle_scanline_spans(p0: (i32, i32), p1: (i32, i32), p2: (i32, i32), mut span: impl FnMut(i32, i32, i32)) {
+    let mut triangle = [p0, p1, p2];
+    triangle.sort_by_key(|&(_, y)| y);
+    let [top, middle, bottom] = triangle;
 
-    /// Changes the current stroke mode, see StrokeMode
-    pub fn stroke_mode(&mut self, mode: StrokeMode) {
+    if top.1 == bottom.1 {return;}
+
+    // flat bottom part : from the top vertex down to the middle row (excluded)
+    if middle.1 > top.1 {
+        triangle_half_spans((top, middle), (top, bottom), top.1, middle.1, &mut span);
+    }
+    // flat top part : from the middle row down to the bottom vertex
+    if bottom.1 > middle.1 {
+        triangle_half_spans((middle, bottom), (top, bottom), middle.1, bottom.1, &mut span);
+    }
+}
+
+/// Computes the barycentric weights of point p relative to the triangle p0,p1,p2 (None if the triangle is flat),
+/// the weights sum to 1 and are all within 0..=1 when p lies inside the triangle
+fn barycentric_weights(p0: (i32, i32), p1: (i32, i32), p2: (i32, i32), p: (f32, f32)) -> Option<(f32, f32, f32)> {
+    let area = ((p1.0 - p0.0) * (p2.1 - p0.1) - (p2.0 - p0.0) * (p1.1 - p0.1)) as f32;
+    if area == 0.0 {return None;}
+
+    let cross = |a: (i32, i32), b: (i32, i32)| {
+        (a.0 as f32 - p.0) * (b.1 as f32 - p.1) - (b.0 as f32 - p.0) * (a.1 as f32 - p.1)
+    };
+    let w0 = cross(p1, p2) / area;
+    let w1 = cross(p2, p0) / area;
+    Some((w0, w1, 1.0 - w0 - w1))
+}
+
+/// Loads an image file into an ImageBuffer usable by Sketch::image (converted to rgb)
+pub fn load_image(file_path: &str) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>, String> {
+    image::open(file_path)
+        .map(|image| image.to_rgb8())
+        .map_err(|e| format!("Unable to load image '{}' : {}", file_path, e))
+}
+
+/// Returns a copy of the image mirrored left to right
+pub fn flip_horizontal(image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    image::imageops::flip_horizontal(image_buffer)
+}
+
+/// Returns a copy of the image mirrored top to bottom
+pub fn flip_vertical(image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    image::imageops::flip_vertical(image_buffer)
+}
+
+/// Returns a copy of the image rotated clockwise by times * 90 degrees (negative times rotate counterclockwise)
+pub fn rotate_90(image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>, times: i32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    match times.rem_euclid(4) {
+        1 => image::imageops::rotate90(image_buffer),
+        2 => image::imageops::rotate180(image_buffer),
+        3 => image::imageops::rotate270(image_buffer),
+        _ => image_buffer.clone(),
+    }
+}
+
+/// Collects the characters typed into the window, shared with the Sketch which drains it every frame
+struct TypedCharsCallback {
+    buffer: Rc<RefCell<Vec<char>>>,
+}
+
+impl minifb::InputCallback for TypedCharsCallback {
+    fn add_char(&mut self, uni_char: u32) {
+        if let Some(c) = char::from_u32(uni_char) {
+            self.buffer.borrow_mut().push(c);
+        }
+    }
+}
+
+/// Snapshot of the drawing style, see Sketch::push_style
+struct Style {
+    fill_color: Option<u32>,
+    fill_gradient: Option<(u32, u32, bool)>,
+    stroke_color: Option<u32>,
+    stroke_weight: i32,
+    stroke_mode: StrokeMode,
+    stroke_cap: StrokeCap,
+    stroke_join: StrokeJoin,
+    blend_mode: BlendMode,
+    opacity: f32,
+    tint: Option<u32>,
+}
+
+/// Offscreen pixel buffer, drawn into with Sketch::draw_into and composited with Sketch::draw_graphics
+pub struct Graphics {
+    buffer: PixelBuffer,
+}
+
+impl Graphics {
+    /// Creates a fully transparent offscreen buffer
+    pub fn new(width: usize, height: usize) -> Self {
+        Graphics {buffer: PixelBuffer::new(width, height)}
+    }
+
+    /// Returns the width of the buffer
+    pub fn width(&self) -> usize {
+        self.buffer.width
+    }
+
+    /// Returns the height of the buffer
+    pub fn height(&self) -> usize {
+        self.buffer.height
+    }
+
+    /// Returns the whole pixel buffer, row-major : pixel x,y is at index x + y * width
+    pub fn pixels(&self) -> &[u32] {
+        &self.buffer.pixels
+    }
+
+    /// Returns the color of the pixel at x,y, or None if x,y is outside the buffer
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        self.buffer.get_pixel(x, y)
+    }
+}
+
+/// Row-major pixel buffer rasterized into by Sketch, either the window buffer or the one of a Graphics
+struct PixelBuffer {
+    pixels: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl PixelBuffer {
+    /// Creates a buffer of width by height pixels set to 0
+    fn new(width: usize, height: usize) -> Self {
+        PixelBuffer {pixels: vec![0u32; width*height], width, height}
+    }
+
+    /// Returns the color of the pixel at x,y, or None if x,y is outside the buffer
+    fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {return None;}
+
+        Some(self.pixels[x as usize + y as usize * self.width])
+    }
+}
+
+/// 2D camera applied to drawing coordinates before they reach the window, x,y is the world point shown
+/// at the drawing origin and zoom scales distances around it, see Sketch::camera_translate and Sketch::camera_zoom.
+/// Shapes, lines, images and graphics follow the camera, text is moved but keeps its size, and pixel level
+/// functions (fill_pixel, stroke_pixel, get_pixel, pixels, flood_fill, filters, clip) ignore it and address buffer
+/// pixels directly, see Sketch::supersample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {x: 0f32, y: 0f32, zoom: 1f32}
+    }
+}
+
+impl Camera {
+    /// Converts a world point to view coordinates (relative to the drawing origin, before the coordinate mode)
+    pub fn world_to_view(&self, x: f32, y: f32) -> (f32, f32) {
+        ((x - self.x) * self.zoom, (y - self.y) * self.zoom)
+    }
+
+    /// Converts view coordinates back to a world point, inverse of Camera::world_to_view
+    pub fn view_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        (x / self.zoom + self.x, y / self.zoom + self.y)
+    }
+}
+
+/// Image split into a grid of equally sized frames, numbered left to right then top to bottom
+pub struct SpriteSheet {
+    image: ImageBuffer<Rgb<u8>, Vec<u8>>,
+    frame_width: u32,
+    frame_height: u32,
+    columns: u32,
+    rows: u32,
+}
+
+impl SpriteSheet {
+    /// Creates a sprite sheet of frame_width x frame_height frames, partial frames at the right and bottom are ignored
+    pub fn new(image: ImageBuffer<Rgb<u8>, Vec<u8>>, frame_width: u32, frame_height: u32) -> Self {
+        assert!(frame_width > 0 && frame_height > 0, "Error: sprite sheet frames must have a positive size !");
+
+        let columns = image.width() / frame_width;
+        let rows = image.height() / frame_height;
+        SpriteSheet {image, frame_width, frame_height, columns, rows}
+    }
+
+    /// Returns the number of frames in the sheet
+    pub fn frame_count(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    /// Returns the source rectangle x,y,w,h of frame index within the image, indices wrap around the frame count
+    pub fn frame_rect(&self, index: usize) -> Option<(i32, i32, i32, i32)> {
+        if self.frame_count() == 0 {return None;}
+
+        let index = (index % self.frame_count()) as u32;
+        Some((
+            ((index % self.columns) * self.frame_width) as i32,
+            ((index / self.columns) * self.frame_height) as i32,
+            self.frame_width as i32,
+            self.frame_height as i32,
+        ))
+    }
+
+    /// Draws frame index (wrapping around the frame count) with its top left corner at x,y
+    pub fn draw_frame<S: State>(&self, sketch: &mut Sketch<S>, index: usize, x: i32, y: i32) {
+        if let Some((sx, sy, sw, sh)) = self.frame_rect(index) {
+            sketch.image_region(&self.image, sx, sy, sw, sh, x, y);
+        }
+    }
+}
+
+pub trait State : Default {}
+
+pub struct Sketch<S: State> {
+    window: minifb::Window,
+    // what is being drawn on, the window buffer or a Graphics during Sketch::draw_into
+    target: PixelBuffer,
+
+    pub width: usize,
+    pub height: usize,
+    supersample: usize,
+
+    pub is_looping: bool,
+    pub frame_count: u32,
+    pub delta_time: f32,
+    last_frame_time: std::time::SystemTime,
+    max_delta_time: Option<f32>,
+    frame_time: f32,
+    start_time: std::time::Instant,
+    smoothed_framerate: f32,
+    target_fps: Option<usize>,
+    fixed_timestep_fps: Option<usize>,
+
+    recording_path: Option<String>,
+    recorded_frames: Vec<(usize, usize, Vec<u32>)>,
+
+    pub mouse_x: f32,
+    pub mouse_y: f32,
+    pub pmouse_x: f32,
+    pub pmouse_y: f32,
+    pub mouse_is_pressed: bool,
+    pub mouse_button: MouseButton,
+    pub mouse_scroll: (f32, f32),
+    typed_chars: Vec<char>,
+    typed_chars_buffer: Rc<RefCell<Vec<char>>>,
+
+    fill_color: Option<u32>,
+    fill_gradient: Option<(u32, u32, bool)>,
+    stroke_color: Option<u32>,
+    stroke_weight: i32,
+    stroke_mode: StrokeMode,
+    stroke_cap: StrokeCap,
+    stroke_join: StrokeJoin,
+    stroke_mask: Option<Vec<(i32, i32)>>,
+    stroke_dash: Vec<i32>,
+
+    shape_vertices: Vec<(i32, i32)>,
+    shape_holes: Vec<usize>,
+    shape_type: ShapeType,
+
+    image_sampling: ImageSampling,
+    polygon_fill_method: PolygonFillMethod,
+    fill_rule: FillRule,
+    clip_rect: Option<(usize, usize, usize, usize)>,
+    coord_mode: CoordMode,
+    coord_origin: Option<(i32, i32)>,
+    camera: Camera,
+    curve_tolerance: f32,
+    blend_mode: BlendMode,
+    opacity: f32,
+    tint: Option<u32>,
+    linear_blending: bool,
+    smooth_circles: bool,
+
+    pub draw_method: Option<fn(&mut Self)>,
+    pub setup_method: Option<fn(&mut Self)>,
+    pub mouse_pressed_method: Option<fn(&mut Self)>,
+    pub mouse_released_method: Option<fn(&mut Self)>,
+    pub mouse_wheel_method: Option<fn(&mut Self, f32, f32)>,
+    pub resize_method: Option<fn(&mut Self)>,
+    resizable: bool,
+    window_scale: usize,
+    pub key_pressed_method: Option<fn(&mut Self, Key)>,
+    pub key_released_method: Option<fn(&mut Self, Key)>,
+
+    style_stack: Vec<Style>,
+
+    loaded_fonts: Vec<(Font, String)>,
+    font_index: usize,
+    glyph_cache: HashMap<(usize, char, u32), (Metrics, Vec<u8>)>,
+    text_spacing: i32,
+
+    pub state: S,
+}
+
+#[allow(dead_code)]
+impl<S: State> Sketch<S> {
+    /// Initializes a Sketch, panics on invalid dimensions, see Sketch::try_from_size
+    pub fn from_size(width: usize, height: usize, state: S) -> Sketch<S> {
+        Self::from_size_with_options(width, height, state, minifb::WindowOptions::default())
+    }
+
+    /// Initializes a Sketch, fails if a dimension is 0 or too large or if the window can't be opened
+    pub fn try_from_size(width: usize, height: usize, state: S) -> Result<Sketch<S>, String> {
+        Self::try_from_size_with_options(width, height, state, minifb::WindowOptions::default())
+    }
+
+    /// Initializes a Sketch with a resizable window, see Sketch::resize_method
+    pub fn from_size_resizable(width: usize, height: usize, state: S) -> Sketch<S> {
+        Self::from_size_with_options(width, height, state, minifb::WindowOptions {
+            resize: true,
+            ..minifb::WindowOptions::default()
+        })
+    }
+
+    /// Initializes a Sketch with the given minifb window options (borderless, scale, topmost, ...),
+    /// width and height are the size of the drawing area before window scaling
+    pub fn from_size_with_options(width: usize, height: usize, state: S, options: minifb::WindowOptions) -> Sketch<S> {
+        Self::try_from_size_with_options(width, height, state, options).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of Sketch::from_size_with_options
+    pub fn try_from_size_with_options(width: usize, height: usize, state: S, options: minifb::WindowOptions) -> Result<Sketch<S>, String> {
+        validate_dimensions(width, height)?;
+
+        let resizable = options.resize;
+        let window_scale = match options.scale {
+            minifb::Scale::X2 => 2,
+            minifb::Scale::X4 => 4,
+            minifb::Scale::X8 => 8,
+            minifb::Scale::X16 => 16,
+            minifb::Scale::X32 => 32,
+            minifb::Scale::X1 | minifb::Scale::FitScreen => 1,
+        };
+        let window = minifb::Window::new(DEFAULT_NAME, width, height, options)
+            .map_err(|e| format!("Unable to open window: {}", e))?;
+
+        let mut sketch = Sketch {
+            window,
+            target: PixelBuffer::new(width, height),
+            width,
+            height,
+            supersample: 1,
+            is_looping: true,
+            frame_count: 0,
+            delta_time: 0.0,
+            last_frame_time: std::time::SystemTime::now(),
+            max_delta_time: Some(0.1),
+            frame_time: 0.0,
+            start_time: std::time::Instant::now(),
+            smoothed_framerate: 0.0,
+            target_fps: None,
+            fixed_timestep_fps: None,
+
+            recording_path: None,
+            recorded_frames: Vec::new(),
+            mouse_x: 0.0,
+            mouse_y: 0.0,
+            pmouse_x: 0.0,
+            pmouse_y: 0.0,
+            mouse_is_pressed: false,
+            mouse_button: MouseButton::Left,
+            mouse_scroll: (0.0, 0.0),
+            typed_chars: Vec::new(),
+            typed_chars_buffer: Rc::new(RefCell::new(Vec::new())),
+            fill_color: Some(0),
+            fill_gradient: None,
+            stroke_color: Some(0),
+            stroke_weight: 1,
+            stroke_mode: StrokeMode::Circle,
+            stroke_cap: StrokeCap::Round,
+            stroke_join: StrokeJoin::Round,
+            stroke_mask: None,
+            stroke_dash: Vec::new(),
+            shape_vertices: Vec::new(),
+            shape_holes: Vec::new(),
+            shape_type: ShapeType::Polygon,
+
+            image_sampling: ImageSampling::Nearest,
+            polygon_fill_method: PolygonFillMethod::Scanline,
+            fill_rule: FillRule::EvenOdd,
+            clip_rect: None,
+            coord_mode: CoordMode::YDown,
+            coord_origin: None,
+            camera: Camera::default(),
+            curve_tolerance: 0.25,
+            blend_mode: BlendMode::Normal,
+            opacity: 1.0,
+            tint: None,
+            linear_blending: false,
+            smooth_circles: false,
+
+            draw_method: None,
+            setup_method: None,
+            mouse_pressed_method: None,
+            mouse_released_method: None,
+            mouse_wheel_method: None,
+            resize_method: None,
+            resizable,
+            window_scale,
+            key_pressed_method: None,
+            key_released_method: None,
+
+            style_stack: Vec::new(),
+
+            loaded_fonts: Vec::new(),
+            font_index: 0,
+            glyph_cache: HashMap::new(),
+            text_spacing: 0,
+
+            state,
+        };
+
+        sketch.window.set_input_callback(Box::new(TypedCharsCallback {buffer: sketch.typed_chars_buffer.clone()}));
+
+        { // Loads default fonts (TimesNewRoman and Arial)
+            let tnr_file_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts/Times New Roman.ttf");
+            let tnr_file_path_str = tnr_file_path.to_str().unwrap();
+            let times_new_roman = Self::open_ttf_file(tnr_file_path_str);
+
+            let arial_file_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts/Arial.ttf");
+            let arial_file_path_str = arial_file_path.to_str().unwrap();
+            let arial = Self::open_ttf_file(arial_file_path_str);
+
+            sketch.loaded_fonts.push((times_new_roman, tnr_file_path_str.to_string()));
+            sketch.loaded_fonts.push((arial, arial_file_path_str.to_string()));
+        }
+
+        Ok(sketch)
+    }
+
+    // Private Methods
+
+    /// INTERNAL : interface between Sketch and minifb for mouse interactions
+    fn handle_mouse(&mut self) {
+        (self.pmouse_x, self.pmouse_y) = (self.mouse_x, self.mouse_y);
+        (self.mouse_x, self.mouse_y) = self.window.get_mouse_pos(MouseMode::Clamp).unwrap();
+        if self.frame_count == 0 {
+            // no previous frame, avoids a jump from the origin
+            (self.pmouse_x, self.pmouse_y) = (self.mouse_x, self.mouse_y);
+        }
+
+        let temp = self.mouse_is_pressed;
+        if self.window.get_mouse_down(MouseButton::Left) {
+            self.mouse_is_pressed = true;
+            self.mouse_button = MouseButton::Left;
+        }
+        else if self.window.get_mouse_down(MouseButton::Right) {
+            self.mouse_is_pressed = true;
+            self.mouse_button = MouseButton::Right;
+        }
+        else if self.window.get_mouse_down(MouseButton::Middle) {
+            self.mouse_is_pressed = true;
+            self.mouse_button = MouseButton::Middle;
+        }
+        else {
+            if temp {
+                if let Some(mouse_released_method) = self.mouse_released_method {
+                    mouse_released_method(self);
+                }
+            }
+            self.mouse_is_pressed = false;
+        }
+
+        if !temp && self.mouse_is_pressed {
+            if let Some(mouse_pressed_method) = self.mouse_pressed_method {
+                mouse_pressed_method(self);
+            }
+        }
+
+        self.mouse_scroll = self.window.get_scroll_wheel().unwrap_or((0.0, 0.0));
+        if self.mouse_scroll != (0.0, 0.0) {
+            if let Some(mouse_wheel_method) = self.mouse_wheel_method {
+                mouse_wheel_method(self, self.mouse_scroll.0, self.mouse_scroll.1);
+            }
+        }
+    }
+
+    /// INTERNAL : interface between Sketch and minifb for window resizing (reallocates the pixel buffer)
+    fn handle_resize(&mut self) {
+        if !self.resizable {
+            return;
+        }
+
+        let (window_width, window_height) = self.window.get_size();
+        let (width, height) = (window_width / self.window_scale, window_height / self.window_scale);
+        if (width, height) == (self.width, self.height) || width == 0 || height == 0 {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.target.width = width * self.supersample;
+        self.target.height = height * self.supersample;
+        self.target.pixels = vec![0u32; self.target.width*self.target.height];
+        // a clip rectangle set for the previous size must not reach past the new buffer
+        self.clip_rect = self.clip_rect.map(|clip_rect| {
+            intersect_region(clip_rect, Some((0, 0, self.target.width, self.target.height))).unwrap_or((0, 0, 0, 0))
+        });
+
+        if let Some(resize_method) = self.resize_method {
+            resize_method(self);
+        }
+    }
+
+    /// INTERNAL : interface between Sketch and minifb for keyboard interactions
+    fn handle_keys(&mut self) {
+        self.typed_chars = std::mem::take(&mut *self.typed_chars_buffer.borrow_mut());
+
+        let keys_pressed:Vec<Key> = self.window.get_keys_pressed(KeyRepeat::No);
+
+        for key in keys_pressed {
+            if let Some(key_pressed_method) = self.key_pressed_method {
+                key_pressed_method(self, key);
+            }
+        }
+
+        let keys_released:Vec<Key> = self.window.get_keys_released();
+
+        for key in keys_released {
+            if let Some(key_released_method) = self.key_released_method {
+                key_released_method(self, key);
+            }
+        }
+    }
+
+    /// main loop of the Sketch
+    pub fn run(&mut self) {
+        self.start_time = std::time::Instant::now();
+        self.setup_method.expect("Setup method was not set !")(self);
+
+        self.last_frame_time = std::time::SystemTime::now();
+
+        while self.step_frame() {}
+    }
+
+    /// Runs a single iteration of the main loop (input, draw, window update),
+    /// returns false once the window is closed. Unlike Sketch::run, the setup method is not called
+    pub fn step_frame(&mut self) -> bool {
+        if !self.window.is_open() {
+            return false;
+        }
+
+        self.handle_resize();
+
+        if self.is_looping {
+            // the real frame time is kept for the framerate, delta_time may be adjusted for the simulation
+            self.frame_time = self.last_frame_time.elapsed().unwrap().as_secs_f32();
+            self.delta_time = self.frame_time;
+            if let Some(fps) = self.fixed_timestep_fps {
+                // deterministic mode, every frame advances by the same amount whatever the real elapsed time
+                self.delta_time = 1.0 / fps as f32;
+            }
+            else if let Some(max_delta_time) = self.max_delta_time {
+                // a long stall (pause, window drag) should not make animations jump
+                self.delta_time = self.delta_time.min(max_delta_time);
+            }
+            self.last_frame_time = std::time::SystemTime::now();
+
+            let framerate = self.get_framerate();
+            self.smoothed_framerate = if self.smoothed_framerate == 0.0 {framerate}
+                else {self.smoothed_framerate * 0.9 + framerate * 0.1};
+
+            self.handle_mouse();
+            self.handle_keys();
+
+            self.draw_method.expect("Draw method was not set !")(self);
+
+            if self.recording_path.is_some() {
+                // the window may be resized while recording, every frame keeps its own size
+                let frame = self.output_pixels().into_owned();
+                self.recorded_frames.push((self.width, self.height, frame));
+            }
+        }
+
+        if self.supersample == 1 {
+            self.window.update_with_buffer(&self.target.pixels, self.width, self.height).unwrap();
+        } else {
+            let frame = box_downsample(&self.target.pixels, self.target.width, self.target.height, self.supersample);
+            self.window.update_with_buffer(&frame, self.width, self.height).unwrap();
+        }
+
+        if self.is_looping {
+            self.frame_count += 1;
+        }
+
+        self.window.is_open()
+    }
+
+    /// Loads a Font from a ttf file path
+    fn open_ttf_file(file_path: &str) -> Font {
+        let mut file_content = Vec::new();
+        fs::File::open(file_path).unwrap().read_to_end(&mut file_content).unwrap();
+
+        Font::from_bytes(file_content, FontSettings::default()).unwrap()
+    }
+
+    /// Generates a mask based on current stroke mode
+    fn generate_mask(&self) -> Vec<(i32, i32)> {
+        match self.stroke_mode {
+            StrokeMode::Circle => circular_mask(self.buffer_stroke_weight()),
+            StrokeMode::Square => square_mask(self.buffer_stroke_weight()),
+            StrokeMode::Custom(mask_func) => mask_func(self.buffer_stroke_weight()),
+        }
+    }
+
+    /// Takes the cached stroke mask, generating it if stroke weight or mode changed since last use
+    /// (give it back with Sketch::store_mask once done)
+    fn take_mask(&mut self) -> Vec<(i32, i32)> {
+        self.stroke_mask.take().unwrap_or_else(|| self.generate_mask())
+    }
+
+    /// Puts back a mask obtained from Sketch::take_mask into the cache
+    fn store_mask(&mut self, mask: Vec<(i32, i32)>) {
+        self.stroke_mask = Some(mask);
+    }
+
+    /// Pastes mask on Sketch at certain x,y coordinates
+    fn apply_mask_as_stroke(&mut self, x: i32, y: i32, mask: &[(i32, i32)]) {
+        for (i, j) in mask {
+            let (xi, yj) = (x + *i, y + *j);
+            self.stroke_pixel(xi, yj);
+        }
+    }
+
+    /// Applies mask along traced line, with flat_ends mask pixels lying beyond the endpoints are discarded
+    fn bresenham_plot_line_mask(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, mask: &[(i32, i32)], flat_ends: bool) {
+        // steps whose mask can't reach the window are skipped, the dash pattern still starts at x0,y0
+        let margin = mask.iter().map(|(i, j)| i.abs().max(j.abs())).max().unwrap_or(0);
+        let (first_position, points_to_plot) = bresenham_line_clipped(x0, y0, x1, y1, self.target.width, self.target.height, margin);
+
+        let (dx, dy) = (x1 as i64 - x0 as i64, y1 as i64 - y0 as i64);
+        let length_sq = dx * dx + dy * dy;
+
+        for (index, point) in points_to_plot.into_iter().enumerate() {
+            if !self.is_dash_on((first_position + index) as i32) {continue;}
+            if !flat_ends {
+                self.apply_mask_as_stroke(point.0, point.1, mask);
+                continue;
+            }
+
+            for (i, j) in mask {
+                let (xi, yj) = (point.0 + *i, point.1 + *j);
+
+                // projection of the pixel on the line, scaled by the line length
+                let t = (xi as i64 - x0 as i64) * dx + (yj as i64 - y0 as i64) * dy;
+                if t < 0 || t > length_sq {continue;}
+
+                self.stroke_pixel(xi, yj);
+            }
+        }
+    }
+
+    /// Checks if the dash pattern is drawing at a given position (in pixels) along a line
+    fn is_dash_on(&self, position: i32) -> bool {
+        let period: i32 = self.stroke_dash.iter().sum();
+        if period <= 0 {return true;}
+
+        // the pattern is measured in window pixels
+        let mut position = (position / self.supersample as i32) % period;
+        for (k, length) in self.stroke_dash.iter().enumerate() {
+            if position < *length {
+                return k % 2 == 0;
+            }
+            position -= length;
+        }
+        true
+    }
+
+    /// Strokes a filled disc of radius r at xc,yc (used for round caps)
+    fn stroke_disc(&mut self, xc: i32, yc: i32, r: i32) {
+        for xi in -r..=r {
+            for yi in -r..=r {
+                if xi*xi + yi*yi > r*r {continue;}
+                self.stroke_pixel(xc + xi, yc + yi);
+            }
+        }
+    }
+
+    /// Plots a point on all octants of a space around xc,yc origin
+    fn plot_on_all_octants(points: &mut Vec<(i32, i32)>, xc: i32, yc: i32, x: i32, y: i32) {
+        points.push((xc + x, yc + y));
+        points.push((xc + y, yc + x));
+
+        points.push((xc - x, yc + y));
+        points.push((xc - y, yc + x));
+
+        points.push((xc + x, yc - y));
+        points.push((xc + y, yc - x));
+
+        points.push((xc - x, yc - y));
+        points.push((xc - y, yc - x));
+    }
+
+    /// Traces a circle using the Bresenham algorithm (Midpoint algorithm)
+    fn bresenham_plot_circle(&mut self, xc: i32, yc: i32, r: i32) -> Vec<(i32, i32)> {
+        let mut points: Vec<(i32, i32)> = vec![];
+
+        let (mut x, mut y) = (0, r);
+        let mut d = 3 - 2 * r;
+
+        while y >= x {
+            Self::plot_on_all_octants(&mut points, xc, yc, x, y);
+            if d > 0 {
+                y -= 1;
+                d += 4 * (x - y) + 10;
+            }
+            else {
+                d += 4 * x + 6;
+            }
+            x += 1;
+        }
+        points
+    }
+
+    /// Applies stroke mask along traced circle
+    fn circle_stroke(&mut self, xc: i32, yc: i32, r: i32) {
+        let reach = r.saturating_add(self.buffer_stroke_weight());
+        if !self.bounds_intersect(xc.saturating_sub(reach), yc.saturating_sub(reach),
+                                  xc.saturating_add(reach), yc.saturating_add(reach)) {return;}
+
+        if self.smooth_circles {
+            self.circle_stroke_smooth(xc, yc, r);
+            return;
+        }
+
+        let mask = self.take_mask();
+        let circle = self.bresenham_plot_circle(xc, yc, r);
+        for (x, y) in circle {
+            self.apply_mask_as_stroke(x, y, &mask)
+        }
+        self.store_mask(mask);
+    }
+
+    /// Draws an anti-aliased outline of the circle at xc,yc with radius r, covering the same band as the masked
+    /// outline (stroke_weight pixels on each side) and blending pixels by their coverage
+    fn circle_stroke_smooth(&mut self, xc: i32, yc: i32, r: i32) {
+        let Some(color) = self.stroke_color else {return;};
+        let half_width = (self.stroke_weight as f32 + 0.5) * self.supersample as f32;
+        let r = r as f32;
+
+        // pixels further than this from the center are not covered
+        let outer = r + half_width + 0.5;
+        let inner = r - half_width - 0.5;
+        let reach = outer.ceil() as i32;
+
+        let yi_start = (-reach).max(yc.saturating_neg());
+        let yi_end = reach.min((self.target.height as i32 - 1).saturating_sub(yc));
+        for yi in yi_start..=yi_end {
+            // squared in f32, the offsets of a large circle centered far off screen overflow i32 products
+            let dy_sq = (yi as f32).powi(2);
+            if dy_sq >= outer * outer {continue;}
+            let x_outer = (outer * outer - dy_sq).sqrt().ceil() as i32;
+            // the hole of the band on this row, if any
+            let x_inner = if inner > 0f32 && dy_sq < inner * inner {(inner * inner - dy_sq).sqrt().floor() as i32} else {-1};
+
+            for xi in (-x_outer).max(xc.saturating_neg())..=x_outer.min((self.target.width as i32 - 1).saturating_sub(xc)) {
+                if xi.abs() <= x_inner {continue;}
+
+                let distance = ((xi as f32).powi(2) + dy_sq).sqrt();
+                let coverage = circle_outline_coverage(distance, r, half_width);
+                if coverage <= 0f32 {continue;}
+
+                let alpha = (RgbaColor::color_alpha(color) as f32 * coverage).round() as u32;
+                self.change_pixel(xc + xi, yc + yi, (color & 0x00FFFFFF) | (alpha << 24));
+            }
+        }
+    }
+
+    /// Fills a circular region of the sketch using a brute-force algorithm
+    fn circle_fill(&mut self, xc: i32, yc: i32, r: i32) {
+        if !self.bounds_intersect(xc.saturating_sub(r), yc.saturating_sub(r),
+                                  xc.saturating_add(r), yc.saturating_add(r)) {return;}
+
+        // only iterate the rows that are on screen
+        let yi_start = (-r).max(-yc);
+        let yi_end = r.min(self.target.height as i32 - 1 - yc);
+
+        let r_sq = r as i64 * r as i64;
+        for yi in yi_start..=yi_end {
+            // widest xi such that xi*xi + yi*yi <= r*r
+            let half_width = integer_sqrt(r_sq - yi as i64 * yi as i64) as i32;
+
+            self.fill_span(yc + yi, xc.saturating_sub(half_width), xc.saturating_add(half_width));
+        }
+    }
+
+    /// Fills the pixels of the circle centered at xc,yc with radius r whose angle lies between start_angle and end_angle
+    fn sector_fill(&mut self, xc: i32, yc: i32, r: i32, start_angle: f32, end_angle: f32) {
+        if !self.bounds_intersect(xc.saturating_sub(r), yc.saturating_sub(r),
+                                  xc.saturating_add(r), yc.saturating_add(r)) {return;}
+
+        let yi_start = (-r).max(-yc);
+        let yi_end = r.min(self.target.height as i32 - 1 - yc);
+        let xi_min = -xc;
+        let xi_max = self.target.width as i32 - 1 - xc;
+
+        let r_sq = r as i64 * r as i64;
+        for yi in yi_start..=yi_end {
+            let half_width = integer_sqrt(r_sq - yi as i64 * yi as i64) as i32;
+
+            // consecutive pixels inside the angle range are filled as a single span
+            let mut run_start: Option<i32> = None;
+            for xi in (-half_width).max(xi_min)..=half_width.min(xi_max) {
+                let inside = angle_in_range((yi as f32).atan2(xi as f32), start_angle, end_angle);
+                match (inside, run_start) {
+                    (true, None) => run_start = Some(xi),
+                    (false, Some(start)) => {
+                        self.fill_span(yc + yi, xc + start, xc + xi - 1);
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(start) = run_start {
+                self.fill_span(yc + yi, xc + start, xc + half_width.min(xi_max));
+            }
+        }
+    }
+
+    /// Fills the pixels at offsets xi,yi from xc,yc with inner_r^2 <= xi^2 + yi^2 <= outer_r^2
+    fn ring_fill(&mut self, xc: i32, yc: i32, inner_r: i32, outer_r: i32) {
+        if !self.bounds_intersect(xc.saturating_sub(outer_r), yc.saturating_sub(outer_r),
+                                  xc.saturating_add(outer_r), yc.saturating_add(outer_r)) {return;}
+
+        let yi_start = (-outer_r).max(-yc);
+        let yi_end = outer_r.min(self.target.height as i32 - 1 - yc);
+
+        let (outer_sq, inner_sq) = (outer_r as i64 * outer_r as i64, inner_r as i64 * inner_r as i64);
+        for yi in yi_start..=yi_end {
+            let yi_sq = yi as i64 * yi as i64;
+            let outer_half = integer_sqrt(outer_sq - yi_sq) as i32;
+
+            if inner_sq > yi_sq {
+                // widest xi such that xi*xi + yi*yi < inner_r*inner_r, these pixels form the hole
+                let inner_half = integer_sqrt(inner_sq - yi_sq - 1) as i32;
+                self.fill_span(yc + yi, xc.saturating_sub(outer_half), xc - inner_half - 1);
+                self.fill_span(yc + yi, xc + inner_half + 1, xc.saturating_add(outer_half));
+            }
+            else {
+                self.fill_span(yc + yi, xc.saturating_sub(outer_half), xc.saturating_add(outer_half));
+            }
+        }
+    }
+
+    /// Returns true if the box x_min,y_min to x_max,y_max (inclusive) overlaps the canvas
+    fn bounds_intersect(&self, x_min: i32, y_min: i32, x_max: i32, y_max: i32) -> bool {
+        x_max >= 0 && y_max >= 0 && x_min < self.target.width as i32 && y_min < self.target.height as i32
+    }
+
+    /// Fills a triangle by separating into flat bottom and flat top parts
+    fn triangle_fill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, x2: i32, y2: i32) {
+        triangle_scanline_spans((x0, y0), (x1, y1), (x2, y2), |y, x_start, x_end| {
+            self.fill_span(y, x_start, x_end);
+        });
+    }
+
+    /// Fills a triangle, interpolating the vertex colors c0, c1, c2 across its face
+    #[allow(clippy::too_many_arguments)]
+    fn triangle_fill_gouraud(&mut self, x0: i32, y0: i32, c0: u32, x1: i32, y1: i32, c1: u32, x2: i32, y2: i32, c2: u32) {
+        let (p0, p1, p2) = ((x0, y0), (x1, y1), (x2, y2));
+        let channel = |color: u32, shift: u32| ((color >> shift) & 255) as f32;
+
+        triangle_scanline_spans(p0, p1, p2, |y, x_start, x_end| {
+            if y < 0 || y >= self.target.height as i32 {return;}
+
+            for x in x_start.max(0)..=x_end.min(self.target.width as i32 - 1) {
+                let Some((w0, w1, w2)) = barycentric_weights(p0, p1, p2, (x as f32 + 0.5, y as f32 + 0.5)) else {return;};
+
+                let color = [24, 16, 8, 0].iter().fold(0u32, |color, &shift| {
+                    let value = channel(c0, shift) * w0 + channel(c1, shift) * w1 + channel(c2, shift) * w2;
+                    color | ((value.round().clamp(0.0, 255.0) as u32) << shift)
+                });
+                self.change_pixel(x, y, color);
+            }
+        });
+    }
+
+    /// Strokes the 3 sides of a triangle
+    fn triangle_stroke(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, x2: i32, y2: i32) {
+        self.plot_line(x0, y0, x1, y1);
+        self.plot_line(x1, y1, x2, y2);
+        self.plot_line(x2, y2, x0, y0);
+        self.stroke_screen_joins(&[(x0, y0), (x1, y1), (x2, y2)], true);
+    }
+
+    /// Fills pixels x_start..=x_end of row y with the current fill color, every shape fill goes through here
+    fn fill_span(&mut self, y: i32, x_start: i32, x_end: i32) {
+        if let Some(color) = self.fill_color {
+            self.fill_span_color(y, x_start, x_end, color);
+        }
+    }
+
+    /// Fills pixels x_start..=x_end of row y with color, clipped to the window
+    fn fill_span_color(&mut self, y: i32, x_start: i32, x_end: i32, color: u32) {
+        if y < 0 || y >= self.target.height as i32 {return;}
+
+        for x in x_start.max(0)..=x_end.min(self.target.width as i32 - 1) {
+            self.change_pixel(x, y, color);
+        }
+    }
+
+    /// General method for changing the color of a pixel (includes bound checks)
+    fn change_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if x < 0 ||y < 0 ||x >= self.target.width as i32 || y >= self.target.height as i32 {return;}
+
+        let (x, y) = (x as u32, y as u32);
+
+        let color = if self.opacity < 1f32 {
+            let alpha = (RgbaColor::color_alpha(color) as f32 * self.opacity).round() as u8;
+            (color & 0x00FFFFFF) | ((alpha as u32) << 24)
+        } else {color};
+
+        if RgbaColor::color_alpha(color) == 255 && matches!(self.blend_mode, BlendMode::Normal) {
+            self.set_pixel(x, y, color);
+        }
+        else {
+            self.mix_pixel(x, y, color);
+        }
+    }
+
+    /// Changes the color of the pixel at x,y
+    fn set_pixel(&mut self, x: u32, y: u32, color: u32) {
+        if self.is_clipped(x, y) {return;}
+
+        let index = x as usize + y as usize * self.target.width;
+        self.target.pixels[index] = color;
+    }
+
+    /// Returns the window pixel where the drawing origin lies, see Sketch::coordinate_origin
+    fn screen_origin(&self) -> (i32, i32) {
+        self.coord_origin.unwrap_or(match self.coord_mode {
+            CoordMode::YDown => (0, 0),
+            CoordMode::YUp => (0, self.height as i32 - 1),
+        })
+    }
+
+    /// Converts a point from drawing coordinates to window pixels, applying the camera then the coordinate mode
+    fn to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        let (origin_x, origin_y) = self.screen_origin();
+        let (view_x, view_y) = self.camera.world_to_view(x as f32, y as f32);
+        let (view_x, view_y) = match self.coord_mode {
+            CoordMode::YDown => (view_x, view_y),
+            CoordMode::YUp => (view_x, -view_y),
+        };
+
+        // rounded after supersampling so that the extra resolution keeps sub-pixel positions
+        let scale = self.supersample as f32;
+        (
+            self.scale_to_buffer(origin_x).saturating_add((view_x * scale).round() as i32),
+            self.scale_to_buffer(origin_y).saturating_add((view_y * scale).round() as i32),
+        )
+    }
+
+    /// Converts a length (like a radius) from drawing coordinates to window pixels
+    fn to_screen_length(&self, length: i32) -> i32 {
+        (length as f32 * self.camera.zoom * self.supersample as f32).round() as i32
+    }
+
+    /// Converts a window pixel coordinate to the center of its block in the supersampled buffer
+    fn scale_to_buffer(&self, value: i32) -> i32 {
+        let factor = self.supersample as i32;
+        value.saturating_mul(factor).saturating_add((factor - 1) / 2)
+    }
+
+    /// Stroke weight in buffer pixels, so that strokes keep their window thickness when supersampling
+    fn buffer_stroke_weight(&self) -> i32 {
+        let factor = self.supersample as i32;
+        self.stroke_weight * factor + (factor - 1) / 2
+    }
+
+    /// Returns the frame as shown in the window, box-downsampled if supersampling is enabled
+    fn output_pixels(&self) -> std::borrow::Cow<'_, [u32]> {
+        if self.supersample == 1 {
+            std::borrow::Cow::Borrowed(&self.target.pixels)
+        } else {
+            std::borrow::Cow::Owned(box_downsample(&self.target.pixels, self.target.width, self.target.height, self.supersample))
+        }
+    }
+
+    /// Converts an angle from drawing coordinates to window pixels, flipping the y axis mirrors angles
+    fn to_screen_angle(&self, angle: f32) -> f32 {
+        match self.coord_mode {
+            CoordMode::YDown => angle,
+            CoordMode::YUp => -angle,
+        }
+    }
+
+    /// Returns the vertices of the current shape construction converted to window pixels
+    fn screen_vertices(&self) -> Vec<(i32, i32)> {
+        self.shape_vertices.iter().map(|&(x, y)| self.to_screen(x, y)).collect()
+    }
+
+    /// Tests if x,y lies outside the clip rectangle, see Sketch::clip
+    fn is_clipped(&self, x: u32, y: u32) -> bool {
+        match self.clip_rect {
+            Some((x_start, y_start, w, h)) => x < x_start as u32 || y < y_start as u32
+                || x >= (x_start + w) as u32 || y >= (y_start + h) as u32,
+            None => false,
+        }
+    }
+
+    /// Clips a filter region (x, y, w, h) to the canvas, None stands for the whole canvas
+    fn filter_region(&self, region: Option<(i32, i32, i32, i32)>) -> Option<(usize, usize, usize, usize)> {
+        let (x, y, w, h) = region.unwrap_or((0, 0, self.target.width as i32, self.target.height as i32));
+        clip_region(x, y, w, h, self.target.width, self.target.height)
+    }
+
+    /// Restricts a canvas region (x, y, w, h) to the clip rectangle, see Sketch::clip
+    fn within_clip_rect(&self, region: (usize, usize, usize, usize)) -> Option<(usize, usize, usize, usize)> {
+        intersect_region(region, self.clip_rect)
+    }
+
+    /// Changes the color of a pixel by blending with previous color using the current blend mode
+    fn mix_pixel(&mut self, x: u32, y: u32, color: u32) {
+        if self.is_clipped(x, y) {return;}
+
+        let index = x as usize + y as usize * self.target.width;
+        let new_color = RgbaColor::color_blend_color(self.target.pixels[index], color, &self.blend_mode, self.linear_blending);
+        self.target.pixels[index] = new_color;
+    }
+
+    /// Fills the inside of a rectangle at x,y with side lengths w,h
+    fn rect_fill(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        if let Some(gradient) = self.fill_gradient {
+            self.rect_fill_gradient(x, y, w, h, gradient);
+            return;
+        }
+
+        let (x_end, y_end) = (x.saturating_add(w), y.saturating_add(h));
+        if w <= 0 || h <= 0 || !self.bounds_intersect(x, y, x_end - 1, y_end - 1) {return;}
+
+        for j in y.max(0)..y_end.min(self.target.height as i32) {
+            self.fill_span(j, x, x_end - 1);
+        }
+    }
+
+    /// Fills the inside of a rectangle with a linear gradient (color_start, color_end, vertical)
+    /// across its height (vertical) or width
+    fn rect_fill_gradient(&mut self, x: i32, y: i32, w: i32, h: i32, gradient: (u32, u32, bool)) {
+        let (color_start, color_end, vertical) = gradient;
+        let steps = if vertical {h} else {w};
+
+        let (x_end, y_end) = (x.saturating_add(w), y.saturating_add(h));
+        if w <= 0 || h <= 0 || !self.bounds_intersect(x, y, x_end - 1, y_end - 1) {return;}
+
+        // t is still computed from the unclipped k so the gradient does not shift when clipped
+        let (origin, limit) = if vertical {(y, self.target.height as i32)} else {(x, self.target.width as i32)};
+        let k_start = (-origin).max(0);
+        let k_end = steps.min(limit.saturating_sub(origin));
+
+        for k in k_start..k_end {
+            let t = if steps > 1 {k as f32 / (steps - 1) as f32} else {0f32};
+            let color = RgbaColor::lerp_color(color_start, color_end, t);
+
+            if vertical {
+                self.fill_span_color(y + k, x, x_end - 1, color);
+            }
+            else {
+                for j in y.max(0)..y_end.min(self.target.height as i32) {
+                    self.fill_span_color(j, x + k, x + k, color);
+                }
+            }
+        }
+    }
+
+    /// Strokes the 4 sides of a rectangle at x,y with side lengths w,h
+    fn rect_stroke(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        let (x_end, y_end) = (x.saturating_add(w), y.saturating_add(h));
+        self.plot_line(x, y, x_end, y);
+        self.plot_line(x_end, y, x_end, y_end);
+        self.plot_line(x_end, y_end, x, y_end);
+        self.plot_line(x, y_end, x, y);
+        self.stroke_screen_joins(&[(x, y), (x_end, y), (x_end, y_end), (x, y_end)], true);
+    }
+
+    /// Triangulates and fills current constructed polygon
+    fn polygon_fill(&mut self) -> Result<(), ShapeError> {
+        let vertices = self.screen_vertices();
+        let mut coords: Vec<f64> = Vec::new();
+
+        for point in &vertices {
+            coords.push(point.0 as f64);
+            coords.push(point.1 as f64);
+        }
+
+        let triangles = earcutr::earcut(&coords, &self.shape_holes, 2)
+            .map_err(|e| ShapeError::Triangulation(e.to_string()))?;
+
+        for i in 0..(triangles.len() / 3) {
+            let (a, b, c) = (triangles[3*i], triangles[3*i+1], triangles[3*i+2]);
+            self.triangle_fill(
+                vertices[a].0, vertices[a].1,
+                vertices[b].0, vertices[b].1,
+                vertices[c].0, vertices[c].1
+            )
+        }
+        Ok(())
+    }
+
+    /// Fills current constructed polygon row by row, avoiding seams between triangles
+    fn polygon_fill_scanline(&mut self) {
+        let vertices = self.screen_vertices();
+        let holes = std::mem::take(&mut self.shape_holes);
+
+        polygon_scanline_spans(&vertices, &holes, self.fill_rule, 0, self.target.height as i32, |y, x_start, x_end| {
+            self.fill_span(y, x_start, x_end);
+        });
+
+        self.shape_holes = holes;
+    }
+
+    /// Strokes all edges of the current constructed polygon
+    fn polygon_stroke(&mut self) {
+        self.linear_spline(true);
+    }
+
+    /// Draws a polygon based on current shape construction
+    /// (the outline is still drawn if the fill fails)
+    fn polygon(&mut self) -> Result<(), ShapeError> {
+        let mut result = Ok(());
+        if self.fill_color.is_some() {
+            match self.polygon_fill_method {
+                PolygonFillMethod::Scanline => self.polygon_fill_scanline(),
+                PolygonFillMethod::Triangulation => result = self.polygon_fill(),
+            }
+        }
+        if self.stroke_color.is_some() {
+            self.polygon_stroke();
+        }
+        result
+    }
+
+    /// Draws a polygon from a list of points, without altering the current shape construction
+    fn polygon_from_points(&mut self, points: Vec<(i32, i32)>) {
+        let vertices = std::mem::replace(&mut self.shape_vertices, points);
+        let holes = std::mem::take(&mut self.shape_holes);
+
+        // generated polygons are simple, the fill cannot fail
+        let _ = self.polygon();
+
+        self.shape_vertices = vertices;
+        self.shape_holes = holes;
+    }
+
+    /// Draws a linear spline based on the current shape construction, holes separate different chains
+    fn linear_spline(&mut self, loops: bool) {
+        let mut start = 0usize;
+        let mut hole = 0usize;
+        for i in 0..self.shape_vertices.len() {
+            if !loops && (i == self.shape_vertices.len() - 1 || (hole < self.shape_holes.len() && i == self.shape_holes[hole] - 1)) {
+                continue;
+            }
+
+            let (x0, y0) = self.shape_vertices[i];
+            let (x1, y1) = self.shape_vertices[
+                if i == self.shape_vertices.len() - 1 || (hole < self.shape_holes.len() && i == self.shape_holes[hole] - 1) {start}
+                else {i + 1}
+            ];
+
+            self.line(x0, y0, x1, y1);
+
+            if hole < self.shape_holes.len() && i == self.shape_holes[hole] {
+                hole += 1;
+                start = i;
+            }
+        }
+
+        // every contour (outline and holes) is joined on its own
+        let mut bounds = vec![0];
+        bounds.extend(self.shape_holes.iter().copied());
+        bounds.push(self.shape_vertices.len());
+        for contour in bounds.windows(2).filter(|contour| contour[0] < contour[1]) {
+            let points = self.shape_vertices[contour[0]..contour[1]].to_vec();
+            self.stroke_joins(&points, loops);
+        }
+    }
+
+    /// Renders the constructed shape, splines are closed if close is set or if their loops flag is
+    fn render_shape(&mut self, close: bool) -> Result<(), ShapeError> {
+        match self.shape_type {
+            ShapeType::Polygon => {
+                self.polygon()?;
+            }
+            ShapeType::LinearSpline {loops} => {
+                self.linear_spline(loops || close);
+            }
+            ShapeType::CubicBezierSpline {loops} => {
+                self.cubic_bezier_spline(loops || close);
+            }
+            ShapeType::QuadraticBezierSpline {loops} => {
+                self.quadratic_bezier_spline(loops || close);
+            }
+        }
+        Ok(())
+    }
+
+    // Public Methods
+
+    /// Changes the name of the window
+    pub fn name(&mut self, name: &str) {
+        self.window.set_title(name);
+    }
+
+    /// Returns how far the mouse moved since the previous frame
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        (self.mouse_x - self.pmouse_x, self.mouse_y - self.pmouse_y)
+    }
+
+    /// Checks if the key: Key is currently pressed
+    pub fn key_is_down(&self, key: Key) -> bool{
+        self.window.is_key_down(key)
+    }
+
+    /// Checks if either shift key is currently pressed
+    pub fn is_shift_down(&self) -> bool {
+        self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift)
+    }
+
+    /// Checks if either ctrl key is currently pressed
+    pub fn is_ctrl_down(&self) -> bool {
+        self.window.is_key_down(Key::LeftCtrl) || self.window.is_key_down(Key::RightCtrl)
+    }
+
+    /// Checks if either alt key is currently pressed
+    pub fn is_alt_down(&self) -> bool {
+        self.window.is_key_down(Key::LeftAlt) || self.window.is_key_down(Key::RightAlt)
+    }
+
+    /// Returns the characters typed since the previous frame (shift and layout applied, no control characters)
+    pub fn typed_chars(&self) -> &[char] {
+        &self.typed_chars
+    }
+
+    /// Stops the animation loop
+    pub fn no_loop(&mut self) {
+        self.is_looping = false;
+    }
+
+    /// Sets the maximum delta_time reported to the draw method in seconds (0.1 by default), None disables the limit
+    pub fn max_delta_time(&mut self, max_delta_time: Option<f32>) {
+        self.max_delta_time = max_delta_time;
+    }
+
+    /// Makes delta_time a fixed 1 / fps seconds every frame regardless of the real elapsed time,
+    /// so that animations (and recordings) are reproducible frame for frame, None restores wall-clock timing
+    pub fn set_fixed_timestep(&mut self, fps: Option<usize>) {
+        assert!(fps != Some(0), "Error: a fixed timestep needs a non-zero framerate !");
+        self.fixed_timestep_fps = fps;
+    }
+
+    /// Returns the number of milliseconds elapsed since the Sketch started running (counts even when not looping)
+    pub fn millis(&self) -> u128 {
+        self.start_time.elapsed().as_millis()
+    }
+
+    /// Returns the framerate of the last frame (0 before the first frame), measured from the real frame time
+    /// so that slow frames show even when delta_time is clamped or fixed
+    pub fn get_framerate(&self) -> f32 {
+        framerate_from_frame_time(self.frame_time)
+    }
+
+    /// Returns the framerate averaged over the last frames, less jittery than Sketch::get_framerate
+    pub fn get_smoothed_framerate(&self) -> f32 {
+        self.smoothed_framerate
+    }
+
+    /// Sets the framerate limit of the window
+    pub fn framerate(&mut self, fps: usize) {
+        self.window.set_target_fps(fps);
+        self.target_fps = Some(fps);
+    }
+
+    /// Saves the current drawing style (fill, stroke, stroke weight, mode, cap and join, blend mode, opacity)
+    pub fn push_style(&mut self) {
+        self.style_stack.push(Style {
+            fill_color: self.fill_color,
+            fill_gradient: self.fill_gradient,
+            stroke_color: self.stroke_color,
+            stroke_weight: self.stroke_weight,
+            stroke_mode: self.stroke_mode.clone(),
+            stroke_cap: self.stroke_cap.clone(),
+            stroke_join: self.stroke_join,
+            blend_mode: self.blend_mode.clone(),
+            opacity: self.opacity,
+            tint: self.tint,
+        });
+    }
+
+    /// Restores the last drawing style saved with Sketch::push_style (does nothing if none was saved)
+    pub fn pop_style(&mut self) {
+        if let Some(style) = self.style_stack.pop() {
+            self.fill_color = style.fill_color;
+            self.fill_gradient = style.fill_gradient;
+            self.stroke_color = style.stroke_color;
+            self.stroke_weight = style.stroke_weight;
+            self.stroke_mode = style.stroke_mode;
+            self.stroke_cap = style.stroke_cap;
+            self.stroke_join = style.stroke_join;
+            self.blend_mode = style.blend_mode;
+            self.opacity = style.opacity;
+            self.tint = style.tint;
+            self.stroke_mask = None;
+        }
+    }
+
+    /// Sets the current fill color
+    pub fn fill(&mut self, color: u32) {
+        self.fill_color = Some(color);
+        self.fill_gradient = None;
+    }
+
+    /// Sets a linear gradient fill from color_start to color_end, going down (vertical) or right,
+    /// used by rectangles (other shapes are filled with color_start), call Sketch::fill to go back to solid
+    pub fn fill_gradient(&mut self, color_start: u32, color_end: u32, vertical: bool) {
+        self.fill_color = Some(color_start);
+        self.fill_gradient = Some((color_start, color_end, vertical));
+    }
+
+    /// Removes current fill color, drawn shapes will be hollow
+    pub fn no_fill(&mut self) {
+        self.fill_color = None;
+        self.fill_gradient = None;
+    }
+
+    /// Returns the current fill color (None after Sketch::no_fill)
+    pub fn get_fill(&self) -> Option<u32> {
+        self.fill_color
+    }
+
+    /// Returns the current stroke color (None after Sketch::no_stroke)
+    pub fn get_stroke(&self) -> Option<u32> {
+        self.stroke_color
+    }
+
+    /// Sets the current stroke color
+    pub fn stroke(&mut self, color: u32) {
+        self.stroke_color = Some(color);
+    }
+
+    /// Removes the current stroke color, drawn shapes will not have an outline
+    pub fn no_stroke(&mut self) {
+        self.stroke_color = None;
+    }
+
+    /// Sets the thickness of the outline, 0 draws single pixel lines and negative weights are clamped to 0
+    pub fn stroke_weight(&mut self, weight: i32) {
+        self.stroke_weight = weight.max(0);
+        self.stroke_mask = None;
+    }
+
+    /// Changes the current stroke mode, see StrokeMode
+    pub fn stroke_mode(&mut self, mode: StrokeMode) {
         self.stroke_mode = mode;
+        self.stroke_mask = None;
+    }
+
+    /// Sets a global opacity between 0 and 1 multiplying the alpha of everything drawn (1 is fully opaque)
+    pub fn opacity(&mut self, alpha: f32) {
+        self.opacity = alpha.clamp(0.0, 1.0);
+    }
+
+    /// Sets a tint color multiplying every drawn image pixel, its alpha fades the images
+    pub fn tint(&mut self, color: u32) {
+        self.tint = Some(color);
+    }
+
+    /// Removes the current tint, images will be drawn with their own colors
+    pub fn no_tint(&mut self) {
+        self.tint = None;
+    }
+
+    /// Multiplies every channel of an image pixel by the current tint (if any)
+    fn apply_tint(&self, color: u32) -> u32 {
+        tint_color(color, self.tint)
+    }
+
+    /// Enables blending in linear light instead of directly on sRGB values (disabled by default),
+    /// semi-transparent overlaps and gradients look more correct but blending is noticeably slower
+    pub fn linear_blending(&mut self, enabled: bool) {
+        self.linear_blending = enabled;
+    }
+
+    /// Renders at factor times the window resolution (1 by default, which disables it), frames are box-downsampled
+    /// for the window, screenshots and recordings. Shapes, lines, text and images keep using window coordinates and
+    /// stroke weights (images are upsampled to cover the same window area) while pixel level functions (pixel access,
+    /// filters, clip) address the larger buffer directly : buffer pixel x,y lies within window pixel x / factor, y / factor,
+    /// so window positions like mouse_x, mouse_y are multiplied by factor to reach it. Clears the canvas and the clip rectangle
+    pub fn supersample(&mut self, factor: usize) {
+        assert!(factor >= 1, "Error: supersampling factor must be at least 1 !");
+        assert!(self.width * factor <= MAX_DIMENSION && self.height * factor <= MAX_DIMENSION,
+                "Error: supersampled size must be at most {} !", MAX_DIMENSION);
+
+        self.supersample = factor;
+        self.target.width = self.width * factor;
+        self.target.height = self.height * factor;
+        self.target.pixels = vec![0u32; self.target.width * self.target.height];
+        self.clip_rect = None;
+        self.stroke_mask = None;
     }
 
-    /// Applies current fill color to pixel at x,y
+    /// Enables anti-aliased circle outlines (disabled by default), edge pixels are blended by coverage.
+    /// Smooth outlines are always round and solid, ignoring the stroke mode and dash pattern
+    pub fn smooth_circles(&mut self, enabled: bool) {
+        self.smooth_circles = enabled;
+    }
+
+    /// Changes the way drawn colors are composed with the existing pixels, see BlendMode
+    pub fn blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
+
+    /// Changes the algorithm used to fill polygons, see PolygonFillMethod
+    pub fn polygon_fill_method(&mut self, method: PolygonFillMethod) {
+        self.polygon_fill_method = method;
+    }
+
+    /// Changes the orientation of the y axis used by shapes, lines and text anchors (CoordMode::YDown by default).
+    /// With CoordMode::YUp positive y goes up and angles turn counterclockwise, text and images stay upright.
+    /// Only the top left corner of images and graphics is transformed, pixel access, filters and clipping always
+    /// use window pixels
+    pub fn coordinate_mode(&mut self, mode: CoordMode) {
+        self.coord_mode = mode;
+    }
+
+    /// Sets the window pixel where the drawing coordinates 0,0 lie, None restores the default
+    /// (top left corner with CoordMode::YDown, bottom left corner with CoordMode::YUp)
+    pub fn coordinate_origin(&mut self, origin: Option<(i32, i32)>) {
+        self.coord_origin = origin;
+    }
+
+    /// Moves the camera by dx,dy world units, see Camera
+    pub fn camera_translate(&mut self, dx: f32, dy: f32) {
+        self.camera.x += dx;
+        self.camera.y += dy;
+    }
+
+    /// Multiplies the camera zoom by factor (above 1 zooms in), distances are scaled around the camera position.
+    /// Stroke weights are not scaled
+    pub fn camera_zoom(&mut self, factor: f32) {
+        assert!(factor > 0f32, "Error: camera zoom factor must be positive !");
+        self.camera.zoom *= factor;
+    }
+
+    /// Replaces the camera, Camera::default() removes any pan and zoom
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+
+    /// Returns the current camera
+    pub fn get_camera(&self) -> Camera {
+        self.camera
+    }
+
+    /// Converts a window position (like mouse_x, mouse_y) to drawing coordinates, undoing the coordinate mode
+    /// and the camera
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (origin_x, origin_y) = self.screen_origin();
+        let (view_x, view_y) = match self.coord_mode {
+            CoordMode::YDown => (x - origin_x as f32, y - origin_y as f32),
+            CoordMode::YUp => (x - origin_x as f32, origin_y as f32 - y),
+        };
+        self.camera.view_to_world(view_x, view_y)
+    }
+
+    /// Restricts all drawing to the rectangle x,y,w,h (negative lengths extend the other way),
+    /// writes outside of it are dropped until Sketch::no_clip is called. Resizing the window keeps the rectangle,
+    /// cut to the new window size
+    pub fn clip(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        // an empty intersection with the canvas clips everything away
+        self.clip_rect = Some(clip_region(x, y, w, h, self.target.width, self.target.height).unwrap_or((0, 0, 0, 0)));
+    }
+
+    /// Removes the clip rectangle set by Sketch::clip
+    pub fn no_clip(&mut self) {
+        self.clip_rect = None;
+    }
+
+    /// Sets how far (in pixels) the segments used to draw Bezier curves may stray from the exact curve (0.25 by default),
+    /// smaller values give smoother curves made of more segments
+    pub fn curve_detail(&mut self, tolerance: f32) {
+        assert!(tolerance > 0f32, "Error: curve tolerance must be positive !");
+        self.curve_tolerance = tolerance;
+    }
+
+    /// Changes the rule deciding which regions of self-overlapping polygons are filled, see FillRule.
+    /// Only honored by PolygonFillMethod::Scanline, triangulation always treats holes as empty
+    pub fn fill_rule(&mut self, rule: FillRule) {
+        self.fill_rule = rule;
+    }
+
+    /// Sets the dash pattern of lines as alternating on and off lengths in pixels (empty for solid lines),
+    /// a pattern with an odd number of lengths is repeated twice so that on and off alternate
+    pub fn stroke_dash(&mut self, pattern: Vec<i32>) {
+        let mut pattern: Vec<i32> = pattern.into_iter().map(|length| length.max(0)).collect();
+        if pattern.len() % 2 == 1 {
+            pattern.extend_from_slice(&pattern.clone());
+        }
+        self.stroke_dash = pattern;
+    }
+
+    /// Removes the current dash pattern, lines will be solid
+    pub fn no_dash(&mut self) {
+        self.stroke_dash.clear();
+    }
+
+    /// Changes the way line ends are drawn, see StrokeCap
+    pub fn stroke_cap(&mut self, cap: StrokeCap) {
+        self.stroke_cap = cap;
+    }
+
+    /// Changes the way connected segments of strokes meet (Round by default, which matches the overlapping round ends
+    /// of the segments), see StrokeJoin. Joints falling in a gap of the dash pattern are left out
+    pub fn stroke_join(&mut self, join: StrokeJoin) {
+        self.stroke_join = join;
+    }
+
+    /// Applies current fill color to pixel at x,y (buffer pixels, see Sketch::supersample)
     pub fn fill_pixel(&mut self, x: i32, y: i32) {
          if let Some(color) = self.fill_color {
              self.change_pixel(x, y, color);
          }
     }
 
-    /// Applies current stroke color to pixel at x,y
+    /// Applies current stroke color to pixel at x,y (buffer pixels, see Sketch::supersample)
     pub fn stroke_pixel(&mut self, x: i32, y: i32) {
         if let Some(color) = self.stroke_color {
             self.change_pixel(x, y, color);
         }
     }
 
-    /// Draws a rectangle at x,y with side lengths w,h
+    /// Returns the color of the pixel at x,y (buffer pixels, see Sketch::supersample), or None if x,y is outside the buffer
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        self.target.get_pixel(x, y)
+    }
+
+    /// Returns the whole pixel buffer, row-major : pixel x,y is at index x + y * width
+    /// (buffer pixels, width and height are multiplied by the supersampling factor)
+    pub fn pixels(&self) -> &[u32] {
+        &self.target.pixels
+    }
+
+    /// Returns the whole pixel buffer for direct writes, row-major : pixel x,y is at index x + y * width
+    /// (no bound checks are performed, x must stay within 0..width and y within 0..height, both multiplied by the
+    /// supersampling factor)
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.target.pixels
+    }
+
+    /// Copies a whole frame into the pixel buffer, row-major : pixel x,y is at index x + y * width
+    /// (panics if data does not hold exactly width * height pixels, both multiplied by the supersampling factor)
+    pub fn set_pixels(&mut self, data: &[u32]) {
+        assert!(data.len() == self.target.pixels.len(), "Error: set_pixels expects {} pixels ({}x{}), got {} !",
+                self.target.pixels.len(), self.target.width, self.target.height, data.len());
+        self.target.pixels.copy_from_slice(data);
+    }
+
+    /// Replaces the 4-connected region of pixels having exactly the color of the pixel at x,y with new_color
+    pub fn flood_fill(&mut self, x: i32, y: i32, new_color: u32) {
+        for index in flood_fill_region(&self.target.pixels, self.target.width, self.target.height, x, y) {
+            self.set_pixel((index % self.target.width) as u32, (index / self.target.width) as u32, new_color);
+        }
+    }
+
+    /// Returns a copy of the whole pixel buffer, see Sketch::restore
+    pub fn snapshot(&self) -> Vec<u32> {
+        self.target.pixels.clone()
+    }
+
+    /// Copies a snapshot back into the pixel buffer, fails if its length is not width * height
+    pub fn restore(&mut self, snapshot: &[u32]) -> Result<(), String> {
+        if snapshot.len() != self.target.pixels.len() {
+            return Err(format!("Snapshot has {} pixels, expected {} ({}x{})", snapshot.len(), self.target.pixels.len(), self.target.width, self.target.height));
+        }
+        self.target.pixels.copy_from_slice(snapshot);
+        Ok(())
+    }
+
+    /// Box blurs the pixels within the rectangle x,y,w,h (clamped to the canvas and the clip rectangle) with the given
+    /// radius (clamped to the region sides), using a horizontal then a vertical pass
+    pub fn blur(&mut self, x: i32, y: i32, w: i32, h: i32, radius: i32) {
+        if radius <= 0 {return;}
+        let Some(region) = clip_region(x, y, w, h, self.target.width, self.target.height).and_then(|region| self.within_clip_rect(region)) else {return;};
+
+        box_blur_region(&mut self.target.pixels, self.target.width, region, radius as usize);
+    }
+
+    /// Adjusts the brightness and contrast of the whole canvas (within the clip rectangle), keeping alpha. In normalized
+    /// channel values (0 to 1) brightness is added (0 keeps the image) and contrast scales around 0.5 (1 keeps the image)
+    pub fn adjust(&mut self, brightness: f32, contrast: f32) {
+        let Some(region) = self.filter_region(None).and_then(|region| self.within_clip_rect(region)) else {return;};
+
+        let adjust_channel = |value: u8| {
+            let value = (value as f32 / 255.0 - 0.5) * contrast + 0.5 + brightness;
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+
+        for_each_row_span(&mut self.target.pixels, self.target.width, region, |_, row| {
+            for color in row.iter_mut() {
+                *color = RgbaColor::argb_color(
+                    RgbaColor::color_alpha(*color),
+                    adjust_channel(RgbaColor::color_red(*color)),
+                    adjust_channel(RgbaColor::color_green(*color)),
+                    adjust_channel(RgbaColor::color_blue(*color)),
+                );
+            }
+        });
+    }
+
+    /// Inverts the rgb channels (255 - value) of the pixels within region (x, y, w, h), or the whole canvas if None,
+    /// keeping alpha (pixels outside the clip rectangle are left as they are)
+    pub fn invert(&mut self, region: Option<(i32, i32, i32, i32)>) {
+        let Some(region) = self.filter_region(region).and_then(|region| self.within_clip_rect(region)) else {return;};
+
+        for_each_row_span(&mut self.target.pixels, self.target.width, region, |_, row| {
+            for color in row.iter_mut() {
+                *color ^= 0x00FFFFFF;
+            }
+        });
+    }
+
+    /// Replaces every block_size x block_size cell of region (x, y, w, h), or of the whole canvas if None,
+    /// with its average color. The region is restricted to the clip rectangle, cells start at its origin and are
+    /// cut short at its right and bottom edges
+    pub fn pixelate(&mut self, block_size: i32, region: Option<(i32, i32, i32, i32)>) {
+        if block_size <= 1 {return;}
+        let Some((x, y, w, h)) = self.filter_region(region).and_then(|region| self.within_clip_rect(region)) else {return;};
+        let block_size = block_size as usize;
+
+        for block_y in (y..y + h).step_by(block_size) {
+            for block_x in (x..x + w).step_by(block_size) {
+                let (x_end, y_end) = ((block_x + block_size).min(x + w), (block_y + block_size).min(y + h));
+                let count = ((x_end - block_x) * (y_end - block_y)) as u32;
+
+                // every cell is read entirely before being written, so cells never read averaged pixels
+                let mut sums = [0u32; 4];
+                for j in block_y..y_end {
+                    for i in block_x..x_end {
+                        let color = self.target.pixels[i + j * self.target.width];
+                        for (channel, sum) in sums.iter_mut().enumerate() {
+                            *sum += (color >> (channel * 8)) & 255;
+                        }
+                    }
+                }
+                let average = sums.iter().enumerate().fold(0u32, |color, (channel, sum)| {
+                    color | (((sum + count / 2) / count) << (channel * 8))
+                });
+
+                for j in block_y..y_end {
+                    self.target.pixels[block_x + j * self.target.width..x_end + j * self.target.width].fill(average);
+                }
+            }
+        }
+    }
+
+    /// Applies a kernel_size x kernel_size convolution kernel (row-major, kernel_size odd) to the rgb channels of the
+    /// pixels within region (x, y, w, h), or the whole canvas if None. Results are clamped to 0..=255 and alpha is kept,
+    /// only pixels within the clip rectangle are written but samples are read from the whole canvas
+    pub fn convolve(&mut self, kernel: &[f32], kernel_size: usize, region: Option<(i32, i32, i32, i32)>) {
+        assert!(kernel_size % 2 == 1, "Error: convolution kernel size must be odd !");
+        assert!(kernel.len() == kernel_size * kernel_size, "Error: convolution kernel must have kernel_size * kernel_size values !");
+        let Some(region) = self.filter_region(region).and_then(|region| self.within_clip_rect(region)) else {return;};
+
+        let source = self.target.pixels.clone();
+        convolve_region(&source, &mut self.target.pixels, self.target.width, self.target.height, region, kernel, kernel_size);
+    }
+
+    /// Converts the pixels within the rectangle x,y,w,h (clamped to the canvas and the clip rectangle) to their luminance,
+    /// 0.299 * red + 0.587 * green + 0.114 * blue, keeping their alpha
+    pub fn grayscale(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        let Some(region) = clip_region(x, y, w, h, self.target.width, self.target.height).and_then(|region| self.within_clip_rect(region)) else {return;};
+
+        for_each_row_span(&mut self.target.pixels, self.target.width, region, |_, row| {
+            for color in row.iter_mut() {
+                *color = RgbaColor::grayscale_color(*color);
+            }
+        });
+    }
+
+    /// Draws a rectangle at x,y with side lengths w,h (negative lengths extend the other way)
     pub fn rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        let (x0, y0) = self.to_screen(x, y);
+        let (x1, y1) = self.to_screen(x.saturating_add(w), y.saturating_add(h));
+        let (x, y, w, h) = normalize_rect(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0));
+
         if self.fill_color.is_some() {
             self.rect_fill(x, y, w, h);
         }
@@ -943,6 +3230,10 @@ impl<S: State> Sketch<S> {
 
     /// Draws a triangle between points x0,y0 x1,y1 and x2,y2
     pub fn triangle(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, x2: i32, y2: i32) {
+        let (x0, y0) = self.to_screen(x0, y0);
+        let (x1, y1) = self.to_screen(x1, y1);
+        let (x2, y2) = self.to_screen(x2, y2);
+
         if self.fill_color.is_some() {
             self.triangle_fill(x0, y0, x1, y1, x2, y2);
         }
@@ -951,8 +3242,25 @@ impl<S: State> Sketch<S> {
         }
     }
 
+    /// Draws a triangle between points x0,y0 x1,y1 and x2,y2 filled with the colors c0, c1, c2 of its vertices
+    /// interpolated across the face (Gouraud shading), the stroke is drawn as with Sketch::triangle
+    #[allow(clippy::too_many_arguments)]
+    pub fn triangle_gouraud(&mut self, x0: i32, y0: i32, c0: u32, x1: i32, y1: i32, c1: u32, x2: i32, y2: i32, c2: u32) {
+        let (x0, y0) = self.to_screen(x0, y0);
+        let (x1, y1) = self.to_screen(x1, y1);
+        let (x2, y2) = self.to_screen(x2, y2);
+
+        self.triangle_fill_gouraud(x0, y0, c0, x1, y1, c1, x2, y2, c2);
+        if self.stroke_color.is_some() {
+            self.triangle_stroke(x0, y0, x1, y1, x2, y2);
+        }
+    }
+
     /// Draws a circle at x,y with radius r
     pub fn circle(&mut self, x: i32, y: i32, r: i32) {
+        let (x, y) = self.to_screen(x, y);
+        let r = self.to_screen_length(r);
+
         if self.fill_color.is_some() {
             self.circle_fill(x, y, r);
         }
@@ -961,45 +3269,615 @@ impl<S: State> Sketch<S> {
         }
     }
 
+    /// Draws a ring (annulus) centered at cx,cy between radii inner_r and outer_r, inner_r is clamped to 0..=outer_r.
+    /// Both circles are stroked if a stroke color is set
+    pub fn ring(&mut self, cx: i32, cy: i32, inner_r: i32, outer_r: i32) {
+        let (cx, cy) = self.to_screen(cx, cy);
+        let (inner_r, outer_r) = (self.to_screen_length(inner_r), self.to_screen_length(outer_r));
+        let inner_r = inner_r.clamp(0, outer_r.max(0));
+
+        if self.fill_color.is_some() {
+            self.ring_fill(cx, cy, inner_r, outer_r);
+        }
+        if self.stroke_color.is_some() {
+            self.circle_stroke(cx, cy, outer_r);
+            self.circle_stroke(cx, cy, inner_r);
+        }
+    }
+
+    /// Draws a pie slice of the circle centered at cx,cy with radius r, between start_angle and end_angle
+    /// (radians, increasing from the x axis towards the y axis, wrapping around 0/2pi).
+    /// The stroke follows the arc and both radii
+    pub fn sector(&mut self, cx: i32, cy: i32, r: i32, start_angle: f32, end_angle: f32) {
+        use std::f32::consts::TAU;
+
+        if self.fill_color.is_some() {
+            // mirroring the y axis also swaps the ends of the angle range
+            let (screen_start, screen_end) = match self.coord_mode {
+                CoordMode::YDown => (start_angle, end_angle),
+                CoordMode::YUp => (self.to_screen_angle(end_angle), self.to_screen_angle(start_angle)),
+            };
+            let (screen_x, screen_y) = self.to_screen(cx, cy);
+            self.sector_fill(screen_x, screen_y, self.to_screen_length(r), screen_start, screen_end);
+        }
+        if self.stroke_color.is_some() {
+            let full_circle = end_angle - start_angle >= TAU;
+            let span = if full_circle {TAU} else {(end_angle - start_angle).rem_euclid(TAU)};
+            let segments = ((r as f32 * span / 4f32).ceil() as usize).clamp(4, 512);
+
+            let arc: Vec<(i32, i32)> = (0..=segments).map(|i| {
+                let angle = start_angle + span * i as f32 / segments as f32;
+                ((cx as f32 + r as f32 * angle.cos()).round() as i32, (cy as f32 + r as f32 * angle.sin()).round() as i32)
+            }).collect();
+
+            self.stroke_points(&arc);
+            if !full_circle {
+                let (first, last) = (arc[0], arc[arc.len() - 1]);
+                self.line(cx, cy, first.0, first.1);
+                self.line(cx, cy, last.0, last.1);
+            }
+        }
+    }
+
+    /// Draws a regular polygon centered at cx,cy with radius r (center to vertex), the first vertex
+    /// is placed at angle rotation (radians), sides must be at least 3
+    pub fn regular_polygon(&mut self, cx: i32, cy: i32, r: i32, sides: u32, rotation: f32) {
+        assert!(sides >= 3, "Error: a regular polygon needs at least 3 sides !");
+
+        let points = (0..sides).map(|i| {
+            let angle = rotation + std::f32::consts::TAU * i as f32 / sides as f32;
+            (
+                (cx as f32 + r as f32 * angle.cos()).round() as i32,
+                (cy as f32 + r as f32 * angle.sin()).round() as i32,
+            )
+        }).collect();
+
+        self.polygon_from_points(points);
+    }
+
+    /// Draws a star centered at cx,cy alternating between outer_r and inner_r over 2 * points vertices,
+    /// the first outer vertex is placed at angle rotation (radians), points must be at least 2
+    pub fn star(&mut self, cx: i32, cy: i32, outer_r: i32, inner_r: i32, points: u32, rotation: f32) {
+        assert!(points >= 2, "Error: a star needs at least 2 points !");
+
+        let vertices = (0..2 * points).map(|i| {
+            let angle = rotation + std::f32::consts::PI * i as f32 / points as f32;
+            let r = if i % 2 == 0 {outer_r} else {inner_r} as f32;
+            (
+                (cx as f32 + r * angle.cos()).round() as i32,
+                (cy as f32 + r * angle.sin()).round() as i32,
+            )
+        }).collect();
+
+        self.polygon_from_points(vertices);
+    }
+
     /// Fills the window with given color
     pub fn background(&mut self, color: u32) {
-        let new_frame: Vec<u32> = vec![color;self.width*self.height];
-        self.pixels = new_frame;
+        // overwrites the buffer in place, keeping its allocation from frame to frame
+        for_each_row_span(&mut self.target.pixels, self.target.width, (0, 0, self.target.width, self.target.height), |_, row| row.fill(color));
     }
 
-    /// Saves a png screenshot of the window
+    /// Saves a png screenshot of the window (panics on failure, see Sketch::save_with_format)
     pub fn save(&mut self, file_path: &str) {
-        let mut image = ImageBuffer::new(self.width as u32, self.height as u32);
+        self.save_with_format(file_path, ImageFormat::Png, None).unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+    }
+
+    /// Saves a screenshot of the window encoded with format, quality (1 to 100, 75 if None)
+    /// is only used by jpeg encoding
+    pub fn save_with_format(&mut self, file_path: &str, format: ImageFormat, quality: Option<u8>) -> Result<(), String> {
+        let pixels = self.output_pixels();
+        let image = ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let pixel: u32 = pixels[x as usize + y as usize * self.width];
+            Rgb([RgbaColor::color_red(pixel), RgbaColor::color_green(pixel), RgbaColor::color_blue(pixel)])
+        });
+
+        if format == ImageFormat::Jpeg {
+            let file = fs::File::create(file_path)
+                .map_err(|e| format!("Unable to save screenshot '{}' : {}", file_path, e))?;
+            let mut writer = std::io::BufWriter::new(file);
+            JpegEncoder::new_with_quality(&mut writer, quality.unwrap_or(75).clamp(1, 100))
+                .encode_image(&image)
+                .map_err(|e| format!("Unable to save screenshot '{}' : {}", file_path, e))
+        }
+        else {
+            image.save_with_format(file_path, format)
+                .map_err(|e| format!("Unable to save screenshot '{}' : {}", file_path, e))
+        }
+    }
+
+    /// Saves a png screenshot of the window keeping the alpha channel of every pixel
+    pub fn save_rgba(&mut self, file_path: &str) -> Result<(), String> {
+        let pixels = self.output_pixels();
+        let image = ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let pixel: u32 = pixels[x as usize + y as usize * self.width];
+            Rgba([RgbaColor::color_red(pixel), RgbaColor::color_green(pixel), RgbaColor::color_blue(pixel), RgbaColor::color_alpha(pixel)])
+        });
+
+        image.save_with_format(file_path, ImageFormat::Png)
+            .map_err(|e| format!("Unable to save screenshot '{}' : {}", file_path, e))
+    }
+
+    /// Starts recording every drawn frame, the animated gif is written to file_path by Sketch::stop_recording
+    /// (restarts the recording if one is already running)
+    pub fn start_recording(&mut self, file_path: &str) {
+        self.recording_path = Some(file_path.to_string());
+        self.recorded_frames.clear();
+    }
+
+    /// Stops the current recording and encodes the recorded frames into an animated gif,
+    /// the frame delay is based on the fixed timestep or else the target framerate (60 fps if none was set).
+    /// If the window was resized while recording, the gif takes the largest size and smaller frames are padded
+    pub fn stop_recording(&mut self) -> Result<(), String> {
+        let Some(file_path) = self.recording_path.take() else {
+            return Ok(());
+        };
+        let frames = std::mem::take(&mut self.recorded_frames);
+
+        let fps = self.fixed_timestep_fps.or(self.target_fps).filter(|&fps| fps > 0).unwrap_or(60) as u32;
+        encode_gif(&file_path, &frames, fps)
+    }
+
+    /// Redirects all drawing done in draw into graphics instead of the window, with the current style.
+    /// The clip rectangle, coordinate origin and camera of the window do not apply to graphics (and are restored
+    /// afterwards, even if draw panics), graphics are never supersampled, see Sketch::supersample
+    pub fn draw_into(&mut self, graphics: &mut Graphics, draw: impl FnOnce(&mut Self)) {
+        let clip_rect = self.clip_rect.take();
+        let coord_origin = self.coord_origin.take();
+        // the camera is applied once, when the graphics are drawn on the window
+        let camera = std::mem::take(&mut self.camera);
+        let supersample = std::mem::replace(&mut self.supersample, 1);
+        if supersample != 1 {self.stroke_mask = None;}
+        self.swap_target(graphics);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| draw(self)));
+
+        self.supersample = supersample;
+        if supersample != 1 {self.stroke_mask = None;}
+        self.swap_target(graphics);
+        self.clip_rect = clip_rect;
+        self.coord_origin = coord_origin;
+        self.camera = camera;
+
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    /// Exchanges the pixel buffer being drawn on with the one of graphics
+    fn swap_target(&mut self, graphics: &mut Graphics) {
+        std::mem::swap(&mut self.target, &mut graphics.buffer);
+        self.width = self.target.width / self.supersample;
+        self.height = self.target.height / self.supersample;
+    }
+
+    /// Converts the rectangle x,y,w,h covered by an image to buffer pixels, applying the camera then the coordinate
+    /// mode to its top left corner (images always go down the window from it, whatever the coordinate mode)
+    fn image_destination(&self, x: i32, y: i32, w: i32, h: i32) -> (i32, i32, i32, i32) {
+        let (origin_x, origin_y) = self.screen_origin();
+        let (view_x, view_y) = self.camera.world_to_view(x as f32, y as f32);
+        let view_y = match self.coord_mode {
+            CoordMode::YDown => view_y,
+            CoordMode::YUp => -view_y,
+        };
+
+        // both edges are rounded so that images placed side by side stay seamless when zoomed
+        let factor = self.supersample as f32;
+        let zoom = self.camera.zoom;
+        let left = (origin_x as f32 + view_x) * factor;
+        let top = (origin_y as f32 + view_y) * factor;
+        let (x0, y0) = (left.round() as i32, top.round() as i32);
+        let (x1, y1) = ((left + w as f32 * zoom * factor).round() as i32, (top + h as f32 * zoom * factor).round() as i32);
+        (x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+    }
+
+    /// Stretches a source_width by source_height grid of colors over the buffer rectangle destination with nearest
+    /// sampling (every source pixel covers a factor x factor block when supersampling), None colors are skipped
+    fn blit_nearest(&mut self, destination: (i32, i32, i32, i32), source_size: (u32, u32), color_at: impl Fn(u32, u32) -> Option<u32>) {
+        let (x, y, w, h) = destination;
+        let (source_width, source_height) = (source_size.0 as i64, source_size.1 as i64);
+        if w <= 0 || h <= 0 || source_width == 0 || source_height == 0 {return;}
+        let Some((x_start, y_start, w_clip, h_clip)) = clip_region(x, y, w, h, self.target.width, self.target.height) else {return;};
+
+        for py in y_start..y_start + h_clip {
+            let v = (py as i64 - y as i64) * source_height / h as i64;
+            for px in x_start..x_start + w_clip {
+                let u = (px as i64 - x as i64) * source_width / w as i64;
+                if let Some(color) = color_at(u as u32, v as u32) {
+                    self.change_pixel(px as i32, py as i32, color);
+                }
+            }
+        }
+    }
+
+    /// Draws an offscreen buffer at x,y, transparent pixels are blended
+    pub fn draw_graphics(&mut self, graphics: &Graphics, x: i32, y: i32) {
+        let (width, height) = (graphics.buffer.width, graphics.buffer.height);
+        let destination = self.image_destination(x, y, width as i32, height as i32);
+
+        self.blit_nearest(destination, (width as u32, height as u32), |i, j| {
+            let color = graphics.buffer.pixels[i as usize + j as usize * width];
+            (RgbaColor::color_alpha(color) != 0).then_some(color)
+        });
+    }
+
+    /// Draws image from ImageBuffer
+    pub fn image(&mut self, image_buffer: ImageBuffer<Rgb<u8>, Vec<u8>>, x: i32, y: i32) {
+        let (width, height) = image_buffer.dimensions();
+        let destination = self.image_destination(x, y, width as i32, height as i32);
+
+        let tint = self.tint;
+        self.blit_nearest(destination, (width, height), |i, j| {
+            let [r, g, b] = image_buffer.get_pixel(i, j).0;
+            Some(tint_color(RgbaColor::rgb_color(r, g, b), tint))
+        });
+    }
+
+    /// Draws image with alpha channel from ImageBuffer, transparent pixels are blended
+    pub fn image_rgba(&mut self, image_buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>, x: i32, y: i32) {
+        let (width, height) = image_buffer.dimensions();
+        let destination = self.image_destination(x, y, width as i32, height as i32);
+
+        let tint = self.tint;
+        self.blit_nearest(destination, (width, height), |i, j| {
+            let [r, g, b, a] = image_buffer.get_pixel(i, j).0;
+            Some(tint_color(RgbaColor::argb_color(a, r, g, b), tint))
+        });
+    }
+
+    /// Draws the sub-rectangle sx,sy,sw,sh of image_buffer with its top left corner at dx,dy,
+    /// the sub-rectangle is clipped to the image and the drawn pixels to the window
+    #[allow(clippy::too_many_arguments)]
+    pub fn image_region(&mut self, image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>, sx: i32, sy: i32, sw: i32, sh: i32, dx: i32, dy: i32) {
+        let Some((x, y, w, h)) = clip_region(sx, sy, sw, sh, image_buffer.width() as usize, image_buffer.height() as usize) else {return;};
+
+        // clipping the source moves the destination by the same amount
+        let (dx, dy) = (dx + x as i32 - sx.min(sx + sw), dy + y as i32 - sy.min(sy + sh));
+        let destination = self.image_destination(dx, dy, w as i32, h as i32);
+
+        let tint = self.tint;
+        self.blit_nearest(destination, (w as u32, h as u32), |i, j| {
+            let [r, g, b] = image_buffer.get_pixel(x as u32 + i, y as u32 + j).0;
+            Some(tint_color(RgbaColor::rgb_color(r, g, b), tint))
+        });
+    }
+
+    /// Changes the sampling used when drawing scaled images, see ImageSampling
+    pub fn image_sampling(&mut self, sampling: ImageSampling) {
+        self.image_sampling = sampling;
+    }
+
+    /// Samples the color of an image at u,v (pixel space) using the current image sampling
+    fn sample_image(&self, image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>, u: f32, v: f32) -> u32 {
+        let (max_x, max_y) = (image_buffer.width() - 1, image_buffer.height() - 1);
+
+        match self.image_sampling {
+            ImageSampling::Nearest => {
+                let (sx, sy) = ((u as u32).min(max_x), (v as u32).min(max_y));
+                let [r, g, b] = image_buffer.get_pixel(sx, sy).0;
+                RgbaColor::rgb_color(r, g, b)
+            }
+            ImageSampling::Bilinear => {
+                let (u, v) = ((u - 0.5).clamp(0.0, max_x as f32), (v - 0.5).clamp(0.0, max_y as f32));
+                let (x0, y0) = (u as u32, v as u32);
+                let (x1, y1) = ((x0 + 1).min(max_x), (y0 + 1).min(max_y));
+                let (tx, ty) = (u - x0 as f32, v - y0 as f32);
+
+                let (p00, p10) = (image_buffer.get_pixel(x0, y0).0, image_buffer.get_pixel(x1, y0).0);
+                let (p01, p11) = (image_buffer.get_pixel(x0, y1).0, image_buffer.get_pixel(x1, y1).0);
+
+                let mut channels = [0u8; 3];
+                for (c, channel) in channels.iter_mut().enumerate() {
+                    let top = p00[c] as f32 * (1f32 - tx) + p10[c] as f32 * tx;
+                    let bottom = p01[c] as f32 * (1f32 - tx) + p11[c] as f32 * tx;
+                    *channel = (top * (1f32 - ty) + bottom * ty).round() as u8;
+                }
+                RgbaColor::rgb_color(channels[0], channels[1], channels[2])
+            }
+        }
+    }
+
+    /// Draws image from ImageBuffer resampled to fit a rectangle at x,y with side lengths w,h
+    pub fn image_scaled(&mut self, image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>, x: i32, y: i32, w: i32, h: i32) {
+        if w <= 0 || h <= 0 || image_buffer.width() == 0 || image_buffer.height() == 0 {return;}
+
+        let (x, y, w, h) = self.image_destination(x, y, w, h);
+        let Some((x_start, y_start, w_clip, h_clip)) = clip_region(x, y, w, h, self.target.width, self.target.height) else {return;};
+        let scale_x = image_buffer.width() as f32 / w as f32;
+        let scale_y = image_buffer.height() as f32 / h as f32;
+
+        for py in y_start..y_start + h_clip {
+            for px in x_start..x_start + w_clip {
+                let (i, j) = (px as i64 - x as i64, py as i64 - y as i64);
+                let color = self.sample_image(image_buffer, (i as f32 + 0.5) * scale_x, (j as f32 + 0.5) * scale_y);
+                self.change_pixel(px as i32, py as i32, self.apply_tint(color));
+            }
+        }
+    }
+
+    /// Draws image from ImageBuffer at x,y rotated by angle (radians, clockwise) around its center,
+    /// using the current image sampling
+    pub fn image_rotated(&mut self, image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>, x: i32, y: i32, angle: f32) {
+        if image_buffer.width() == 0 || image_buffer.height() == 0 {return;}
+
+        let (source_w, source_h) = (image_buffer.width() as f32, image_buffer.height() as f32);
+        let (x, y, w, h) = self.image_destination(x, y, image_buffer.width() as i32, image_buffer.height() as i32);
+        let (w, h) = (w as f32, h as f32);
+        let (cx, cy) = (x as f32 + w / 2f32, y as f32 + h / 2f32);
+        let (sin, cos) = angle.sin_cos();
+
+        // bounding box of the rotated image, clipped to the window
+        let half_w = (w * cos.abs() + h * sin.abs()) / 2f32;
+        let half_h = (w * sin.abs() + h * cos.abs()) / 2f32;
+        let (x_min, x_max) = (((cx - half_w).floor() as i32).max(0), ((cx + half_w).ceil() as i32).min(self.target.width as i32));
+        let (y_min, y_max) = (((cy - half_h).floor() as i32).max(0), ((cy + half_h).ceil() as i32).min(self.target.height as i32));
+
+        for py in y_min..y_max {
+            for px in x_min..x_max {
+                // inverse rotation of the destination pixel center into source space
+                let (dx, dy) = (px as f32 + 0.5 - cx, py as f32 + 0.5 - cy);
+                let (u, v) = (dx * cos + dy * sin + w / 2f32, -dx * sin + dy * cos + h / 2f32);
+                if u < 0f32 || v < 0f32 || u >= w || v >= h {continue;}
+
+                let color = self.sample_image(image_buffer, u * source_w / w, v * source_h / h);
+                self.change_pixel(px, py, self.apply_tint(color));
+            }
+        }
+    }
+
+    /// Draws a line between points x0,y0 and x1,y1
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let (x0, y0) = self.to_screen(x0, y0);
+        let (x1, y1) = self.to_screen(x1, y1);
+        self.plot_line(x0, y0, x1, y1);
+    }
+
+    /// Draws a line between window pixels x0,y0 and x1,y1 with the current stroke cap
+    fn plot_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let mask = self.take_mask();
+
+        match self.stroke_cap {
+            StrokeCap::Butt => {
+                self.bresenham_plot_line_mask(x0, y0, x1, y1, &mask, true);
+            }
+            StrokeCap::Round => {
+                // the mask stamped at the (dash permitting) end pixels forms the cap
+                self.bresenham_plot_line_mask(x0, y0, x1, y1, &mask, false);
+            }
+            StrokeCap::Square => {
+                let (dx, dy) = ((x1 as i64 - x0 as i64) as f32, (y1 as i64 - y0 as i64) as f32);
+                let length = (dx * dx + dy * dy).sqrt();
+
+                // extends both ends by half the line thickness
+                let (ex, ey) = if length == 0f32 {(0, 0)} else {(
+                    (dx / length * self.buffer_stroke_weight() as f32).round() as i32,
+                    (dy / length * self.buffer_stroke_weight() as f32).round() as i32,
+                )};
+                self.bresenham_plot_line_mask(
+                    x0.saturating_sub(ex), y0.saturating_sub(ey), x1.saturating_add(ex), y1.saturating_add(ey), &mask, true,
+                );
+            }
+        }
+
+        self.store_mask(mask);
+    }
+
+    /// Evaluates a cubic Bezier curve into a list of points by adaptive subdivision, the curve is split in half
+    /// until both control points lie within tolerance pixels of the chord
+    fn cubic_bezier_points(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p3: (f32, f32), tolerance: f32) -> Vec<(i32, i32)> {
+        let mut points = vec![(p0.0.round() as i32, p0.1.round() as i32)];
+        cubic_bezier_subdivide(p0, p1, p2, p3, tolerance, 0, &mut points);
+        points
+    }
+
+    /// Draws a cubic Bezier curve from x0,y0 to x1,y1 with control points cx0,cy0 and cx1,cy1
+    #[allow(clippy::too_many_arguments)]
+    pub fn bezier(&mut self, x0: i32, y0: i32, cx0: i32, cy0: i32, cx1: i32, cy1: i32, x1: i32, y1: i32) {
+        let points = Self::cubic_bezier_points(
+            (x0 as f32, y0 as f32),
+            (cx0 as f32, cy0 as f32),
+            (cx1 as f32, cy1 as f32),
+            (x1 as f32, y1 as f32),
+            self.curve_tolerance,
+        );
+
+        self.stroke_points(&points);
+    }
+
+    /// Evaluates a quadratic Bezier curve into a list of points, by adaptive subdivision of the equivalent cubic curve
+    fn quadratic_bezier_points(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), tolerance: f32) -> Vec<(i32, i32)> {
+        // degree elevation : the cubic control points are 2/3 of the way towards the quadratic control point
+        let elevate = |a: (f32, f32)| (a.0 + (p1.0 - a.0) * 2f32 / 3f32, a.1 + (p1.1 - a.1) * 2f32 / 3f32);
+        Self::cubic_bezier_points(p0, elevate(p0), elevate(p2), p2, tolerance)
+    }
+
+    /// Strokes the segments between consecutive points
+    fn stroke_points(&mut self, points: &[(i32, i32)]) {
+        for segment in points.windows(2) {
+            self.line(segment[0].0, segment[0].1, segment[1].0, segment[1].1);
+        }
+    }
+
+    /// Strokes the segments connecting consecutive points, and the last point back to the first if closed
+    /// (does not touch the current shape construction)
+    pub fn polyline(&mut self, points: &[(i32, i32)], closed: bool) {
+        self.stroke_points(points);
+        let closed = closed && points.len() > 2;
+        if closed {
+            let (first, last) = (points[0], points[points.len() - 1]);
+            self.line(last.0, last.1, first.0, first.1);
+        }
+        self.stroke_joins(points, closed);
+    }
+
+    /// Fills the joints between consecutive segments of points (drawing coordinates) with the current stroke join,
+    /// the joints at the first and last points are only filled if closed
+    fn stroke_joins(&mut self, points: &[(i32, i32)], closed: bool) {
+        let points: Vec<(i32, i32)> = points.iter().map(|&(x, y)| self.to_screen(x, y)).collect();
+        self.stroke_screen_joins(&points, closed);
+    }
 
-        for x in 0..self.width as u32 {
-            for y in 0..self.height as u32 {
-                let pixel: u32 = self.pixels[x as usize + y as usize * self.width];
-                image.put_pixel(x, y, Rgb([RgbaColor::color_red(pixel), RgbaColor::color_green(pixel), RgbaColor::color_blue(pixel)]));
+    /// Fills the joints between consecutive segments of points (window pixels) with the current stroke join,
+    /// the joints at the first and last points are only filled if closed
+    fn stroke_screen_joins(&mut self, points: &[(i32, i32)], closed: bool) {
+        if self.stroke_color.is_none() || self.stroke_weight == 0 || points.len() < 3 {return;}
+
+        let weight = self.buffer_stroke_weight();
+        let count = points.len();
+        let joints = if closed {0..count} else {1..count - 1};
+
+        for i in joints {
+            let (a, v, b) = (points[(i + count - 1) % count], points[i], points[(i + 1) % count]);
+
+            // the dash pattern restarts on every segment, so the incoming one ends at its length and the outgoing
+            // one starts at 0, a joint is only drawn where both of them are drawing
+            let incoming_length = (v.0 as i64 - a.0 as i64).abs().max((v.1 as i64 - a.1 as i64).abs());
+            if !self.is_dash_on(incoming_length.min(i32::MAX as i64) as i32) || !self.is_dash_on(0) {continue;}
+
+            if self.stroke_join == StrokeJoin::Round {
+                self.stroke_disc(v.0, v.1, weight);
+                continue;
+            }
+
+            let as_f32 = |(x, y): (i32, i32)| (x as f32, y as f32);
+            let Some(wedge) = join_wedge(as_f32(a), as_f32(v), as_f32(b), weight as f32, self.stroke_join) else {continue;};
+            let wedge: Vec<(i32, i32)> = wedge.iter().map(|(x, y)| (x.round() as i32, y.round() as i32)).collect();
+            for k in 1..wedge.len() - 1 {
+                self.stroke_triangle_fill(wedge[0], wedge[k], wedge[k + 1]);
+            }
+        }
+    }
+
+    /// Fills the triangle p0,p1,p2 (window pixels) with the stroke color
+    fn stroke_triangle_fill(&mut self, p0: (i32, i32), p1: (i32, i32), p2: (i32, i32)) {
+        let Some(color) = self.stroke_color else {return;};
+        triangle_scanline_spans(p0, p1, p2, |y, x_start, x_end| {
+            self.fill_span_color(y, x_start, x_end, color);
+        });
+    }
+
+    /// Draws an arrow from x0,y0 to x1,y1, the shaft is a line and the head a triangle of length head_size
+    /// (and width head_size) pointing at x1,y1, drawn with the current fill and stroke
+    pub fn arrow(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, head_size: i32) {
+        let (tail, tip) = (Vec2::from((x0, y0)), Vec2::from((x1, y1)));
+        let direction = tip.sub(tail).normalize();
+        if direction.length() == 0f32 {return;}
+
+        // the shaft stops at the base of the head so that thick strokes do not poke through the tip
+        let base = tip.sub(direction.scale(head_size as f32));
+        let side = Vec2::new(-direction.y, direction.x).scale(head_size as f32 / 2f32);
+        let (base_x, base_y) = base.into();
+        let (left_x, left_y) = base.add(side).into();
+        let (right_x, right_y) = base.sub(side).into();
+
+        if self.stroke_color.is_some() {
+            self.line(x0, y0, base_x, base_y);
+        }
+        self.triangle(x1, y1, left_x, left_y, right_x, right_y);
+    }
+
+    /// Draws vertical and horizontal lines across the whole window every spacing pixels with the current stroke
+    /// (in window pixels, whatever the coordinate mode)
+    pub fn grid(&mut self, spacing: i32) {
+        self.grid_with_major(spacing, 0, self.stroke_weight);
+    }
+
+    /// Draws a grid like Sketch::grid where every major_every-th line (0 for none) uses the stroke weight major_weight
+    pub fn grid_with_major(&mut self, spacing: i32, major_every: i32, major_weight: i32) {
+        assert!(spacing > 0, "Error: grid spacing must be positive !");
+        if self.stroke_color.is_none() {return;}
+
+        let weight = self.stroke_weight;
+        let (width, height) = (self.width as i32, self.height as i32);
+
+        for (index, offset) in (0..width.max(height)).step_by(spacing as usize).enumerate() {
+            let is_major = major_every > 0 && index as i32 % major_every == 0;
+            let line_weight = if is_major {major_weight} else {weight};
+            if line_weight != self.stroke_weight {
+                self.stroke_weight(line_weight);
             }
+
+            if offset < width {
+                let x = self.scale_to_buffer(offset);
+                self.plot_line(x, 0, x, self.target.height as i32 - 1);
+            }
+            if offset < height {
+                let y = self.scale_to_buffer(offset);
+                self.plot_line(0, y, self.target.width as i32 - 1, y);
+            }
+        }
+
+        if self.stroke_weight != weight {
+            self.stroke_weight(weight);
         }
+    }
+
+    /// Draws a quadratic Bezier curve from x0,y0 to x1,y1 with control point cx,cy
+    pub fn quadratic_bezier(&mut self, x0: i32, y0: i32, cx: i32, cy: i32, x1: i32, y1: i32) {
+        let points = Self::quadratic_bezier_points(
+            (x0 as f32, y0 as f32),
+            (cx as f32, cy as f32),
+            (x1 as f32, y1 as f32),
+            self.curve_tolerance,
+        );
 
-        image.save(file_path).unwrap_or_else(|e| {
-            panic!("Unable to save screenshot : {}", e);
-        });
+        self.stroke_points(&points);
     }
 
-    /// Draws image from ImageBuffer
-    pub fn image(&mut self, image_buffer: ImageBuffer<Rgb<u8>, Vec<u8>>, x: i32, y: i32) {
-        for i in 0..image_buffer.width() {
-            for j in 0..image_buffer.height() {
-                let (px, py) = (x + i as i32, y + j as i32);
-                if px < 0 || py < 0 || px as usize >= self.width || py as usize >= self.height {continue;}
+    /// Draws a cubic Bezier spline based on the current shape construction, holes separate different chains.
+    /// Each chain starts with a vertex followed by bezier vertices (two control points then anchor)
+    fn cubic_bezier_spline(&mut self, loops: bool) {
+        let mut ring_starts: Vec<usize> = vec![0];
+        ring_starts.extend_from_slice(&self.shape_holes);
+        ring_starts.push(self.shape_vertices.len());
+
+        for ring in ring_starts.windows(2) {
+            let (start, end) = (ring[0], ring[1]);
+            if end <= start {continue;}
+
+            let mut anchor = self.shape_vertices[start];
+            let mut i = start + 1;
+            while i + 2 < end {
+                let (control0, control1, next) = (self.shape_vertices[i], self.shape_vertices[i + 1], self.shape_vertices[i + 2]);
+                self.bezier(anchor.0, anchor.1, control0.0, control0.1, control1.0, control1.1, next.0, next.1);
+                anchor = next;
+                i += 3;
+            }
 
-                let (r, g, b, a) = image_buffer.get_pixel(i, j).channels4();
-                self.change_pixel(px, py, RgbaColor::argb_color(a, r, g, b));
+            if loops {
+                let first = self.shape_vertices[start];
+                self.line(anchor.0, anchor.1, first.0, first.1);
             }
         }
     }
 
-    /// Draws a line between points x0,y0 and x1,y1
-    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
-        let mask = self.generate_mask();
-        self.bresenham_plot_line_mask(x0, y0, x1, y1, mask);
+    /// Draws a quadratic Bezier spline based on the current shape construction, holes separate different chains.
+    /// Each chain starts with a vertex followed by quadratic vertices (control point then anchor)
+    fn quadratic_bezier_spline(&mut self, loops: bool) {
+        let mut ring_starts: Vec<usize> = vec![0];
+        ring_starts.extend_from_slice(&self.shape_holes);
+        ring_starts.push(self.shape_vertices.len());
+
+        for ring in ring_starts.windows(2) {
+            let (start, end) = (ring[0], ring[1]);
+            if end <= start {continue;}
+
+            let mut anchor = self.shape_vertices[start];
+            let mut i = start + 1;
+            while i + 1 < end {
+                let (control, next) = (self.shape_vertices[i], self.shape_vertices[i + 1]);
+                self.quadratic_bezier(anchor.0, anchor.1, control.0, control.1, next.0, next.1);
+                anchor = next;
+                i += 2;
+            }
+
+            if loops {
+                let first = self.shape_vertices[start];
+                self.line(anchor.0, anchor.1, first.0, first.1);
+            }
+        }
     }
 
     /// Indicates the start of a shape construction
@@ -1014,27 +3892,53 @@ impl<S: State> Sketch<S> {
         self.shape_vertices.push((x, y));
     }
 
-    /// Indicate start of a hole within the current shape construction
+    /// Add a cubic Bezier segment to current shape construction, from the previous vertex
+    /// to x,y with control points cx0,cy0 and cx1,cy1 (see ShapeType::CubicBezierSpline)
+    pub fn bezier_vertex(&mut self, cx0: i32, cy0: i32, cx1: i32, cy1: i32, x: i32, y: i32) {
+        self.shape_vertices.push((cx0, cy0));
+        self.shape_vertices.push((cx1, cy1));
+        self.shape_vertices.push((x, y));
+    }
+
+    /// Add a quadratic Bezier segment to current shape construction, from the previous vertex
+    /// to x,y with control point cx,cy (see ShapeType::QuadraticBezierSpline)
+    pub fn quadratic_vertex(&mut self, cx: i32, cy: i32, x: i32, y: i32) {
+        self.shape_vertices.push((cx, cy));
+        self.shape_vertices.push((x, y));
+    }
+
+    /// Indicate start of a hole within the current shape construction.
+    /// With FillRule::EvenOdd a hole is always empty, with FillRule::NonZero it is only empty
+    /// if it is wound in the opposite direction of the outer ring
     pub fn begin_hole(&mut self) {
         self.shape_holes.push(self.shape_vertices.len());
     }
 
+    /// Tests if x,y lies inside the current shape construction (holes count as outside),
+    /// see Geometry::point_in_polygon for the behavior on edges
+    pub fn point_in_shape(&self, x: f32, y: f32) -> bool {
+        Geometry::point_in_polygon(&self.shape_vertices, &self.shape_holes, x, y)
+    }
+
     /// Indicate the end of the current shape construction and render constructed shape
+    /// (errors are ignored, see Sketch::try_end_shape)
     pub fn end_shape(&mut self) {
-        match self.shape_type {
-            ShapeType::Polygon => {
-                self.polygon();
-            }
-            ShapeType::LinearSpline {loops} => {
-                self.linear_spline(loops);
-            }
-            ShapeType::CubicBezierSpline {loops} => {
-                todo!()
-            }
-        }
+        let _ = self.try_end_shape();
+    }
+
+    /// Indicate the end of the current shape construction and render constructed shape,
+    /// returns an error instead of panicking if the shape could not be rendered
+    pub fn try_end_shape(&mut self) -> Result<(), ShapeError> {
+        self.render_shape(false)
     }
 
-    /// Changes the current font
+    /// Indicate the end of the current shape construction and render constructed shape, connecting
+    /// the last vertex of each ring back to its first regardless of the loops flag (like endShape(CLOSE))
+    pub fn close_shape(&mut self) {
+        let _ = self.render_shape(true);
+    }
+
+    /// Changes the current font, custom fonts are only read from disk the first time they are selected
     pub fn font(&mut self, font: FontMode) {
         match font {
             FontMode::TimesNewRoman => {
@@ -1044,30 +3948,18 @@ impl<S: State> Sketch<S> {
                 self.font_index = 1;
             }
             FontMode::Custom { file_path } => {
-                let mut i = 0usize;
-                let fonts = &self.loaded_fonts;
-                for (_, fp) in fonts {
-                    if *fp == file_path {
-                        self.font_index = i;
-                        break;
-                    }
-                    i += 1;
-                }
-
-                let new_font = self.open_ttf_file(file_path.as_str());
-                self.loaded_fonts.push((new_font, file_path));
-                self.font_index = self.loaded_fonts.len() - 1;
+                self.font_index = find_or_load_font(&mut self.loaded_fonts, &file_path, Self::open_ttf_file);
             }
         }
     }
 
     /// Renders a single character
-    fn render_char(&mut self, metrics: Metrics, pixels: Vec<u8>, x_start: i32, y_start : i32) {
+    fn render_char(&mut self, metrics: Metrics, pixels: &[u8], x_start: i32, y_start : i32) {
         for i in 0..metrics.width {
             for j in 0..metrics.height {
                 let index = j*metrics.width + i;
                 let (px, py) = (x_start + metrics.xmin + i as i32, y_start + j as i32 - metrics.height as i32 - metrics.ymin);
-                if px < 0 || py < 0 || px as usize >= self.width || py as usize >= self.height {continue;}
+                if px < 0 || py < 0 || px as usize >= self.target.width || py as usize >= self.target.height {continue;}
 
                 let color_to_mix = RgbaColor::argb_color(
                     pixels[index],
@@ -1081,28 +3973,132 @@ impl<S: State> Sketch<S> {
         }
     }
 
-    /// Renders a full string
-    pub fn text(&mut self, string: &str, x: i32, y: i32) {
-        let scale = 32.0;
+    /// Renders a single character whose unrotated pen position is offset from anchor, rotated by angle around anchor.
+    /// Every covered pixel samples the glyph bitmap at its nearest texel
+    fn render_char_rotated(&mut self, metrics: Metrics, pixels: &[u8], offset: (i32, i32), anchor: (i32, i32), angle: f32) {
+        if metrics.width == 0 || metrics.height == 0 {return;}
+
+        // glyph bitmap bounds relative to the anchor, before rotation
+        let left = (offset.0 + metrics.xmin) as f32;
+        let top = (offset.1 - metrics.height as i32 - metrics.ymin) as f32;
+        let (right, bottom) = (left + metrics.width as f32, top + metrics.height as f32);
+
+        let corners = [(left, top), (right, top), (left, bottom), (right, bottom)]
+            .map(|corner| Geometry::rotate_point(corner, (0f32, 0f32), angle));
+        let x_min = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min).floor() as i32;
+        let x_max = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max).ceil() as i32;
+        let y_min = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min).floor() as i32;
+        let y_max = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max).ceil() as i32;
+
+        for dy in y_min..y_max {
+            for dx in x_min..x_max {
+                let (px, py) = (anchor.0 + dx, anchor.1 + dy);
+                if px < 0 || py < 0 || px as usize >= self.target.width || py as usize >= self.target.height {continue;}
+
+                let (lx, ly) = Geometry::rotate_point((dx as f32 + 0.5, dy as f32 + 0.5), (0f32, 0f32), -angle);
+                let (i, j) = ((lx - left).floor() as i32, (ly - top).floor() as i32);
+                if i < 0 || j < 0 || i as usize >= metrics.width || j as usize >= metrics.height {continue;}
+
+                let color_to_mix = RgbaColor::argb_color(
+                    pixels[j as usize * metrics.width + i as usize],
+                    RgbaColor::color_red(self.fill_color.unwrap()),
+                    RgbaColor::color_green(self.fill_color.unwrap()),
+                    RgbaColor::color_blue(self.fill_color.unwrap()),
+                );
+
+                self.mix_pixel(px as u32, py as u32, color_to_mix);
+            }
+        }
+    }
+
+    /// Lays out string from x,y, calling render(sketch, metrics, bitmap, x_start, y_start) with the pen position
+    /// of every glyph, rasterized glyphs are cached across calls
+    fn layout_glyphs(&mut self, string: &str, x: i32, y: i32, mut render: impl FnMut(&mut Self, Metrics, &[u8], i32, i32)) {
+        let scale = TEXT_SIZE * self.supersample as f32;
         let mut x_start = x;
         let mut y_start = y;
 
+        let mut glyph_cache = std::mem::take(&mut self.glyph_cache);
+
         for char in string.chars() {
-            let (metrics, pixels) = {
-                let mut font = &mut self.loaded_fonts[self.font_index].0;
-                font.rasterize(char, scale)
-            };
+            let font = &self.loaded_fonts[self.font_index].0;
+            let (metrics, pixels) = glyph_cache.entry((self.font_index, char, scale.to_bits()))
+                .or_insert_with(|| font.rasterize(char, scale));
+            let metrics = *metrics;
 
-            self.render_char(metrics, pixels, x_start, y_start);
+            render(self, metrics, pixels, x_start, y_start);
 
-            x_start += metrics.advance_width as i32;
+            // negative spacing tightens the text but never moves the pen backwards
+            x_start += (metrics.advance_width as i32 + self.text_spacing * self.supersample as i32).max(0);
             y_start += metrics.advance_height as i32;
         }
+
+        self.glyph_cache = glyph_cache;
+    }
+
+    /// Sets the extra space in pixels added after every character of drawn text (0 by default),
+    /// negative values tighten the text
+    pub fn text_spacing(&mut self, pixels: i32) {
+        self.text_spacing = pixels;
+    }
+
+    /// Returns the file paths identifying the loaded fonts, in loading order (Times New Roman and Arial first)
+    pub fn loaded_font_ids(&self) -> Vec<String> {
+        self.loaded_fonts.iter().map(|(_, file_path)| file_path.clone()).collect()
+    }
+
+    /// Returns the ascent, descent (negative, below the baseline) and line gap of the current font at size in pixels,
+    /// all zero if the font has no horizontal metrics
+    pub fn font_metrics(&self, size: f32) -> (f32, f32, f32) {
+        self.loaded_fonts[self.font_index].0.horizontal_line_metrics(size)
+            .map(|metrics| (metrics.ascent, metrics.descent, metrics.line_gap))
+            .unwrap_or((0f32, 0f32, 0f32))
+    }
+
+    /// Renders string starting at x,y, breaking it at word boundaries so that no line is wider than max_width
+    /// window pixels (a word wider than max_width is put on its own line), lines are spaced by the font line height
+    pub fn text_wrapped(&mut self, string: &str, x: i32, y: i32, max_width: i32) {
+        let font = &self.loaded_fonts[self.font_index].0;
+        let spacing = self.text_spacing as f32;
+        // measured at the window text size, so max_width does not depend on the supersampling factor
+        let lines = wrap_words(string, max_width as f32, |line| {
+            line.chars().map(|c| (font.metrics(c, TEXT_SIZE).advance_width + spacing).max(0f32)).sum()
+        });
+        let line_height = font.horizontal_line_metrics(TEXT_SIZE)
+            .map(|metrics| metrics.new_line_size)
+            .unwrap_or(TEXT_SIZE) * self.supersample as f32;
+
+        // lines always go down the window, whatever the coordinate mode
+        let (x, y) = self.to_screen(x, y);
+        for (i, line) in lines.iter().enumerate() {
+            self.layout_glyphs(line, x, y + (i as f32 * line_height).round() as i32, |sketch, metrics, pixels, x_start, y_start| {
+                sketch.render_char(metrics, pixels, x_start, y_start);
+            });
+        }
+    }
+
+    /// Renders a full string
+    pub fn text(&mut self, string: &str, x: i32, y: i32) {
+        let (x, y) = self.to_screen(x, y);
+        self.layout_glyphs(string, x, y, |sketch, metrics, pixels, x_start, y_start| {
+            sketch.render_char(metrics, pixels, x_start, y_start);
+        });
+    }
+
+    /// Renders a full string rotated by angle (radians, from the x axis towards the y axis) around its starting point x,y
+    pub fn text_rotated(&mut self, string: &str, x: i32, y: i32, angle: f32) {
+        let (x, y) = self.to_screen(x, y);
+        let angle = self.to_screen_angle(angle);
+        self.layout_glyphs(string, x, y, |sketch, metrics, pixels, x_start, y_start| {
+            sketch.render_char_rotated(metrics, pixels, (x_start - x, y_start - y), (x, y), angle);
+        });
     }
 }
 
 
 #[cfg(test)]
+// the original sketch test below is kept as written
+#[allow(unused_mut, clippy::vec_init_then_push)]
 mod tests {
     use super::*;
 
@@ -1135,7 +4131,7 @@ mod tests {
             3f32,
             TransitionTarget::Points {points: start},
             TransitionTarget::Points {points: end},
-        );
+        ).unwrap();
 
         sketch.state.transition = Some(transition);
 
@@ -1173,7 +4169,7 @@ mod tests {
         // sketch.fill(RgbaColor::greyscale_color(255));
         //
         sketch.font(FontMode::TimesNewRoman);
-        let fps =  ((1f32 / sketch.delta_time * 100f32) as u32) as f32 / 100f32;
+        let fps = ((sketch.get_framerate() * 100f32) as u32) as f32 / 100f32;
         sketch.text(format!("FPS : {}", fps).as_str(), 50, 50);
 
 
@@ -1212,4 +4208,707 @@ mod tests {
 
         println!("TESTING DONE")
     }
+
+    #[test]
+    fn repeated_compositing_converges() {
+        let half_white = RgbaColor::argb_color(128, 255, 255, 255);
+        let mut color = RgbaColor::rgb_color(0, 0, 0);
+        for _ in 0..50 {
+            color = RgbaColor::color_alpha_compose_color(color, half_white);
+        }
+        assert_eq!(color, RgbaColor::rgb_color(255, 255, 255));
+
+        let once = RgbaColor::color_alpha_compose_color(RgbaColor::rgb_color(0, 0, 0), half_white);
+        assert_eq!(RgbaColor::color_red(once), 128);
+    }
+
+    #[test]
+    fn negative_rect_sizes_are_normalized() {
+        assert_eq!(normalize_rect(100, 100, 50, 50), (100, 100, 50, 50));
+        assert_eq!(normalize_rect(100, 100, -50, 50), (50, 100, 50, 50));
+        assert_eq!(normalize_rect(100, 100, 50, -50), (100, 50, 50, 50));
+        assert_eq!(normalize_rect(100, 100, -50, -50), (50, 50, 50, 50));
+    }
+
+    #[test]
+    fn point_in_polygon_with_hole() {
+        let vertices = [(0, 0), (10, 0), (10, 10), (0, 10), (3, 3), (7, 3), (7, 7), (3, 7)];
+        let holes = [4usize];
+
+        assert!(Geometry::point_in_polygon(&vertices, &holes, 1.0, 1.0));
+        assert!(!Geometry::point_in_polygon(&vertices, &holes, 5.0, 5.0));
+        assert!(!Geometry::point_in_polygon(&vertices, &holes, 15.0, 5.0));
+
+        // left and top edges are inside, right and bottom edges are outside
+        assert!(Geometry::point_in_polygon(&vertices, &holes, 0.0, 5.0));
+        assert!(Geometry::point_in_polygon(&vertices, &holes, 5.0, 0.0));
+        assert!(!Geometry::point_in_polygon(&vertices, &holes, 10.0, 5.0));
+        assert!(!Geometry::point_in_polygon(&vertices, &holes, 5.0, 10.0));
+    }
+
+    #[test]
+    fn scanline_spans_cover_polygon_area() {
+        let vertices = [(0, 0), (10, 0), (10, 10), (0, 10), (3, 3), (7, 3), (7, 7), (3, 7)];
+        let holes = [4usize];
+
+        let mut covered = 0;
+        polygon_scanline_spans(&vertices, &holes, FillRule::EvenOdd, 0, 100, |_, x_start, x_end| {
+            covered += x_end - x_start + 1;
+        });
+        assert_eq!(covered, 10 * 10 - 4 * 4);
+    }
+
+    #[test]
+    fn fill_rule_decides_same_winding_holes() {
+        // the hole ring has the same winding as the outer ring
+        let vertices = [(0, 0), (10, 0), (10, 10), (0, 10), (3, 3), (7, 3), (7, 7), (3, 7)];
+        let holes = [4usize];
+
+        let mut even_odd = 0;
+        polygon_scanline_spans(&vertices, &holes, FillRule::EvenOdd, 0, 100, |_, x_start, x_end| {
+            even_odd += x_end - x_start + 1;
+        });
+        let mut non_zero = 0;
+        polygon_scanline_spans(&vertices, &holes, FillRule::NonZero, 0, 100, |_, x_start, x_end| {
+            non_zero += x_end - x_start + 1;
+        });
+        assert_eq!(even_odd, 10 * 10 - 4 * 4);
+        assert_eq!(non_zero, 10 * 10);
+
+        // reversing the hole makes it empty with both rules
+        let reversed = [(0, 0), (10, 0), (10, 10), (0, 10), (3, 7), (7, 7), (7, 3), (3, 3)];
+        let mut non_zero = 0;
+        polygon_scanline_spans(&reversed, &holes, FillRule::NonZero, 0, 100, |_, x_start, x_end| {
+            non_zero += x_end - x_start + 1;
+        });
+        assert_eq!(non_zero, 10 * 10 - 4 * 4);
+    }
+
+    #[test]
+    fn gaussian_random_matches_distribution() {
+        let samples: Vec<f32> = (0..20000).map(|_| Geometry::random_gaussian(5f32, 2f32)).collect();
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let variance = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f32>() / samples.len() as f32;
+        assert!((mean - 5f32).abs() < 0.1);
+        assert!((variance.sqrt() - 2f32).abs() < 0.1);
+    }
+
+    #[test]
+    fn random_int_stays_in_inclusive_range() {
+        let mut seen = [false; 4];
+        for _ in 0..1000 {
+            let value = Geometry::random_int(3, 6);
+            assert!((3..=6).contains(&value));
+            seen[(value - 3) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+
+        assert_eq!(Geometry::random_choice::<u8>(&[]), None);
+        assert_eq!(Geometry::random_choice(&[7]), Some(&7));
+    }
+
+    #[test]
+    fn barycentric_weights_interpolate_vertices() {
+        let (p0, p1, p2) = ((0, 0), (30, 0), (0, 30));
+        let (w0, w1, w2) = barycentric_weights(p0, p1, p2, (30.0, 0.0)).unwrap();
+        assert!(w0.abs() < 1e-6 && (w1 - 1.0).abs() < 1e-6 && w2.abs() < 1e-6);
+
+        let (w0, w1, w2) = barycentric_weights(p0, p1, p2, (10.0, 10.0)).unwrap();
+        assert!((w0 - 1.0 / 3.0).abs() < 1e-6 && (w1 - 1.0 / 3.0).abs() < 1e-6 && (w2 - 1.0 / 3.0).abs() < 1e-6);
+
+        assert!(barycentric_weights((0, 0), (5, 5), (10, 10), (1.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn integer_sqrt_is_floor_of_root() {
+        for value in 0..2000i64 {
+            let root = integer_sqrt(value);
+            assert!(root * root <= value && (root + 1) * (root + 1) > value);
+        }
+        assert_eq!(integer_sqrt(-5), 0);
+        assert_eq!(integer_sqrt(i32::MAX as i64 * i32::MAX as i64), i32::MAX as i64);
+    }
+
+    #[test]
+    fn angle_range_wraps_around() {
+        use std::f32::consts::PI;
+        assert!(angle_in_range(0.5, 0.0, 1.0));
+        assert!(!angle_in_range(1.5, 0.0, 1.0));
+        // a range crossing 0 contains angles on both sides
+        assert!(angle_in_range(0.1, 1.5 * PI, 0.5 * PI));
+        assert!(angle_in_range(-0.1, 1.5 * PI, 0.5 * PI));
+        assert!(!angle_in_range(PI, 1.5 * PI, 0.5 * PI));
+        assert!(angle_in_range(PI, 0.0, 2.0 * PI));
+    }
+
+    #[test]
+    fn box_blur_spreads_and_preserves_flat_areas() {
+        let flat = vec![0xFF336699u32; 5];
+        assert_eq!(box_blur_line(&flat, 2), flat);
+
+        // a single white pixel is spread over the window
+        let line = [0xFF000000, 0xFF000000, 0xFFFFFFFF, 0xFF000000, 0xFF000000];
+        let blurred = box_blur_line(&line, 1);
+        assert_eq!(blurred[1], 0xFF555555);
+        assert_eq!(blurred[2], 0xFF555555);
+        assert_eq!(blurred[0], 0xFF000000);
+
+        // pixels outside the region are untouched
+        let mut pixels = vec![0u32; 16];
+        pixels[5] = 0xFFFFFFFF;
+        box_blur_region(&mut pixels, 4, clip_region(1, 1, 2, 2, 4, 4).unwrap(), 1);
+        assert_eq!(pixels[0], 0);
+        assert_ne!(pixels[6], 0);
+        assert_eq!(clip_region(-5, -5, 3, 3, 4, 4), None);
+    }
+
+    #[test]
+    fn convolution_identity_and_edges() {
+        let source: Vec<u32> = (0..9).map(|i| 0xFF000000 | (i * 20) << 16).collect();
+
+        let mut identity = vec![0u32; 9];
+        let kernel = [0f32, 0f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32, 0f32];
+        convolve_region(&source, &mut identity, 3, 3, (0, 0, 3, 3), &kernel, 3);
+        assert_eq!(identity, source);
+
+        // a left shift kernel clamps samples at the left edge
+        let mut shifted = vec![0u32; 9];
+        let kernel = [0f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32, 0f32, 0f32];
+        convolve_region(&source, &mut shifted, 3, 3, (0, 0, 3, 3), &kernel, 3);
+        assert_eq!(shifted[0], source[0]);
+        assert_eq!(shifted[1], source[0]);
+        assert_eq!(shifted[5], source[4]);
+    }
+
+    #[test]
+    fn bezier_points_hit_endpoints_and_midpoints() {
+        let (p0, p1, p2, p3) = ((0f32, 0f32), (0f32, 10f32), (10f32, 10f32), (10f32, 0f32));
+        assert_eq!(Geometry::cubic_bezier_point(p0, p1, p2, p3, 0f32), p0);
+        assert_eq!(Geometry::cubic_bezier_point(p0, p1, p2, p3, 1f32), p3);
+        assert_eq!(Geometry::cubic_bezier_point(p0, p1, p2, p3, 0.5), (5f32, 7.5));
+
+        assert_eq!(Geometry::quadratic_bezier_point(p0, p1, p2, 0f32), p0);
+        assert_eq!(Geometry::quadratic_bezier_point(p0, p1, p2, 1f32), p2);
+        assert_eq!(Geometry::quadratic_bezier_point(p0, p1, p2, 0.5), (2.5, 7.5));
+    }
+
+    #[test]
+    fn adaptive_bezier_follows_the_curve() {
+        let (p0, p1, p2, p3) = ((0f32, 0f32), (0f32, 100f32), (100f32, 100f32), (100f32, 0f32));
+
+        let coarse = Sketch::<MyState>::cubic_bezier_points(p0, p1, p2, p3, 4f32);
+        let fine = Sketch::<MyState>::cubic_bezier_points(p0, p1, p2, p3, 0.25);
+        assert!(fine.len() > coarse.len());
+        assert_eq!(fine[0], (0, 0));
+        assert_eq!(fine[fine.len() - 1], (100, 0));
+
+        // a straight curve needs a single segment
+        let straight = Sketch::<MyState>::cubic_bezier_points(p0, (25f32, 0f32), (75f32, 0f32), p3, 0.25);
+        assert_eq!(straight, vec![(0, 0), (100, 0)]);
+    }
+
+    #[test]
+    fn polar_conversion_round_trips() {
+        let (x, y) = Geometry::polar_to_cartesian(2f32, std::f32::consts::FRAC_PI_2);
+        assert!(x.abs() < 1e-6 && (y - 2f32).abs() < 1e-6);
+
+        let (r, theta) = Geometry::cartesian_to_polar(-3f32, 4f32);
+        let (x, y) = Geometry::polar_to_cartesian(r, theta);
+        assert!((r - 5f32).abs() < 1e-6);
+        assert!((x + 3f32).abs() < 1e-5 && (y - 4f32).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rotate_point_quarter_and_half_turns() {
+        use std::f32::consts::{FRAC_PI_2, PI};
+        let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5;
+
+        assert!(close(Geometry::rotate_point((2f32, 1f32), (1f32, 1f32), FRAC_PI_2), (1f32, 2f32)));
+        assert!(close(Geometry::rotate_point((2f32, 1f32), (1f32, 1f32), PI), (0f32, 1f32)));
+        assert!(close(Geometry::rotate_point((3f32, 4f32), (3f32, 4f32), 1.234), (3f32, 4f32)));
+    }
+
+    #[test]
+    fn words_wrap_within_width() {
+        let measure = |line: &str| line.chars().count() as f32;
+
+        assert_eq!(wrap_words("the quick brown fox", 10f32, measure), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap_words("a verylongword b", 4f32, measure), vec!["a", "verylongword", "b"]);
+        assert_eq!(wrap_words("one\ntwo three", 20f32, measure), vec!["one", "two three"]);
+    }
+
+    #[test]
+    fn reselecting_a_font_does_not_load_it_again() {
+        let file_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts/Arial.ttf");
+        let file_path = file_path.to_str().unwrap();
+
+        let mut loaded_fonts = Vec::new();
+        let mut loads = 0;
+        for _ in 0..3 {
+            let index = find_or_load_font(&mut loaded_fonts, file_path, |path| {
+                loads += 1;
+                Sketch::<MyState>::open_ttf_file(path)
+            });
+            assert_eq!(index, 0);
+        }
+        assert_eq!(loads, 1);
+        assert_eq!(loaded_fonts.len(), 1);
+    }
+
+    #[test]
+    fn sprite_frames_wrap_around() {
+        let sheet = SpriteSheet::new(ImageBuffer::new(64, 40), 16, 16);
+        assert_eq!(sheet.frame_count(), 8);
+        assert_eq!(sheet.frame_rect(0), Some((0, 0, 16, 16)));
+        assert_eq!(sheet.frame_rect(5), Some((16, 16, 16, 16)));
+        assert_eq!(sheet.frame_rect(9), sheet.frame_rect(1));
+
+        let empty = SpriteSheet::new(ImageBuffer::new(8, 8), 16, 16);
+        assert_eq!(empty.frame_rect(0), None);
+    }
+
+    #[test]
+    fn flood_fill_stays_in_connected_region() {
+        // a vertical wall splits a 5x3 buffer in two
+        let mut pixels = vec![0u32; 15];
+        for y in 0..3 {
+            pixels[2 + y * 5] = 1;
+        }
+
+        let mut left = flood_fill_region(&pixels, 5, 3, 0, 0);
+        left.sort();
+        assert_eq!(left, vec![0, 1, 5, 6, 10, 11]);
+        assert_eq!(flood_fill_region(&pixels, 5, 3, 2, 1).len(), 3);
+        assert!(flood_fill_region(&pixels, 5, 3, 7, 0).is_empty());
+
+        // large regions do not overflow the stack
+        assert_eq!(flood_fill_region(&vec![0u32; 1000 * 1000], 1000, 1000, 500, 500).len(), 1000 * 1000);
+    }
+
+    #[test]
+    fn camera_round_trips_points() {
+        let camera = Camera {x: 10f32, y: -4f32, zoom: 2.5};
+        assert_eq!(camera.world_to_view(10f32, -4f32), (0f32, 0f32));
+        assert_eq!(camera.world_to_view(12f32, -4f32), (5f32, 0f32));
+
+        let (x, y) = camera.view_to_world(camera.world_to_view(3f32, 7f32).0, camera.world_to_view(3f32, 7f32).1);
+        assert!((x - 3f32).abs() < 1e-5 && (y - 7f32).abs() < 1e-5);
+        assert_eq!(Camera::default().world_to_view(3f32, 7f32), (3f32, 7f32));
+    }
+
+    #[test]
+    fn spring_settles_on_target() {
+        let mut spring = Spring::new(0f32, 100f32, 4f32);
+        spring.set_target(10f32);
+
+        let mut max_position = 0f32;
+        for _ in 0..600 {
+            spring.step(1f32 / 60f32);
+            max_position = max_position.max(spring.get_position());
+        }
+        assert!(max_position > 10f32, "an under-damped spring overshoots");
+        assert!(spring.is_at_rest(0.01));
+
+        // retargeting keeps the current position
+        spring.set_target(-5f32);
+        assert!((spring.get_position() - 10f32).abs() < 0.01);
+    }
+
+    #[test]
+    fn transition_group_finishes_with_its_longest_transition() {
+        let mut group = TransitionGroup::new();
+        let short = group.add(Transition::initialize(EasingType::Linear, 1f32,
+            TransitionTarget::Point {point: (0, 0)}, TransitionTarget::Point {point: (10, 0)}).unwrap());
+        let long = group.add(Transition::initialize(EasingType::Linear, 2f32,
+            TransitionTarget::Point {point: (0, 0)}, TransitionTarget::Point {point: (0, 20)}).unwrap());
+
+        group.step(1f32);
+        assert!(group.get(short).unwrap().is_finished());
+        assert!(!group.is_finished());
+        assert_eq!(group.get(long).unwrap().get_current_point(), &(0, 10));
+
+        group.step(1f32);
+        assert!(group.is_finished());
+        assert!(group.get(2).is_none());
+    }
+
+    #[test]
+    fn reversed_transition_plays_backwards() {
+        let mut transition = Transition::initialize(EasingType::Linear, 2f32,
+            TransitionTarget::Points {points: vec![(0, 0)]}, TransitionTarget::Points {points: vec![(40, 8)]}).unwrap();
+        transition.step(2f32);
+        assert_eq!(transition.get_current_points(), &vec![(40, 8)]);
+
+        transition.reverse();
+        assert!(!transition.is_finished());
+        assert_eq!(transition.get_current_points(), &vec![(40, 8)]);
+        transition.step(1f32);
+        assert_eq!(transition.get_current_points(), &vec![(20, 4)]);
+        transition.step(1f32);
+        assert_eq!(transition.get_current_points(), &vec![(0, 0)]);
+    }
+
+    #[test]
+    fn transition_rate_scales_time() {
+        let mut transition = Transition::initialize(EasingType::Linear, 4f32,
+            TransitionTarget::Point {point: (0, 0)}, TransitionTarget::Point {point: (100, 0)}).unwrap();
+
+        transition.set_rate(2f32);
+        transition.step(1f32);
+        assert_eq!(transition.get_current_point(), &(50, 0));
+
+        transition.set_rate(0f32);
+        transition.step(1f32);
+        assert_eq!(transition.get_current_point(), &(50, 0));
+
+        transition.set_rate(-1f32);
+        transition.step(1f32);
+        assert_eq!(transition.get_current_point(), &(25, 0));
+        transition.step(10f32);
+        assert_eq!(transition.get_current_point(), &(0, 0));
+    }
+
+    #[test]
+    fn try_accessors_do_not_panic_on_mismatch() {
+        let transition = Transition::initialize(EasingType::Linear, 1f32,
+            TransitionTarget::Point {point: (1, 2)}, TransitionTarget::Point {point: (3, 4)}).unwrap();
+
+        assert_eq!(transition.try_get_current_point(), Some(&(1, 2)));
+        assert_eq!(transition.try_get_end_point(), Some(&(3, 4)));
+        assert_eq!(transition.try_get_current_points(), None);
+        assert_eq!(transition.try_get_start_points(), None);
+    }
+
+    #[test]
+    fn transition_rejects_incompatible_targets() {
+        assert!(Transition::initialize(EasingType::Linear, 1f32,
+            TransitionTarget::Point {point: (0, 0)}, TransitionTarget::Points {points: vec![(0, 0)]}).is_err());
+        assert!(Transition::initialize(EasingType::Linear, 1f32,
+            TransitionTarget::Points {points: vec![(0, 0)]}, TransitionTarget::Points {points: vec![(0, 0), (1, 1)]}).is_err());
+        assert!(Transition::initialize(EasingType::Linear, 1f32,
+            TransitionTarget::Points {points: vec![(0, 0)]}, TransitionTarget::Points {points: vec![(1, 1)]}).is_ok());
+
+        let mut transition = Transition::initialize(EasingType::Linear, 1f32,
+            TransitionTarget::Point {point: (0, 0)}, TransitionTarget::Point {point: (10, 0)}).unwrap();
+        transition.step(0.5);
+        assert!(transition.reset_new(TransitionTarget::Point {point: (0, 0)}, TransitionTarget::Points {points: vec![]}).is_err());
+        assert_eq!(transition.get_current_point(), &(5, 0));
+
+        assert!(transition.reset_new(TransitionTarget::Point {point: (20, 20)}, TransitionTarget::Point {point: (30, 20)}).is_ok());
+        assert_eq!(transition.get_current_point(), &(20, 20));
+        assert!(!transition.is_finished());
+    }
+
+    #[test]
+    fn sketch_dimensions_are_validated() {
+        assert!(validate_dimensions(800, 600).is_ok());
+        assert!(validate_dimensions(0, 600).is_err());
+        assert!(validate_dimensions(800, 0).is_err());
+        assert!(validate_dimensions(MAX_DIMENSION + 1, 1).is_err());
+        assert!(Sketch::try_from_size(0, 0, MyState {transition: None}).is_err());
+    }
+
+    #[test]
+    fn zero_and_negative_weights_give_single_pixel_masks() {
+        for weight in [0, -3] {
+            assert_eq!(circular_mask(weight), vec![(0, 0)]);
+            assert_eq!(square_mask(weight), vec![(0, 0)]);
+        }
+        assert_eq!(circular_mask(1).len(), 5);
+        assert!(square_mask(1).contains(&(1, -1)));
+        assert!(!square_mask(1).contains(&(0, 0)));
+    }
+
+    #[test]
+    fn clipped_lines_keep_the_visible_pixels() {
+        let (width, height, margin) = (40usize, 30usize, 2);
+        let visible = |(x, y): (i32, i32)| {
+            x >= -margin && y >= -margin && x < width as i32 + margin && y < height as i32 + margin
+        };
+        let ends = [(-75, -20), (-3, 12), (0, 0), (17, -60), (39, 29), (58, 7), (20, 95), (-40, 44), (140, -9)];
+
+        for &(x0, y0) in &ends {
+            for &(x1, y1) in &ends {
+                let (_, whole) = bresenham_line_clipped(x0, y0, x1, y1, 1, 1, 1000);
+                assert_eq!(whole.first(), Some(&(x0, y0)));
+                assert_eq!(whole.last(), Some(&(x1, y1)));
+
+                let (first, clipped) = bresenham_line_clipped(x0, y0, x1, y1, width, height, margin);
+                assert_eq!(clipped.as_slice(), &whole[first..first + clipped.len()]);
+                for (position, point) in whole.iter().enumerate() {
+                    if visible(*point) {
+                        assert!(position >= first && position < first + clipped.len());
+                    }
+                }
+            }
+        }
+
+        assert_eq!(cohen_sutherland_clip((-10f64, 5f64), (-1f64, 50f64), (0f64, 0f64, 9f64, 9f64)), None);
+    }
+
+    #[test]
+    fn row_spans_cover_exactly_the_region() {
+        let (width, height) = (300usize, 280usize);
+        for region in [(2, 3, 5, 4), (0, 0, width, height)] {
+            let mut pixels = vec![0u32; width * height];
+            for_each_row_span(&mut pixels, width, region, |j, row| row.fill(j as u32 + 1));
+
+            let (x, y, w, h) = region;
+            for (index, pixel) in pixels.iter().enumerate() {
+                let (i, j) = (index % width, index / width);
+                let inside = i >= x && i < x + w && j >= y && j < y + h;
+                assert_eq!(*pixel, if inside {j as u32 + 1} else {0});
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_circle_coverage_fades_across_the_edges() {
+        // a single pixel wide outline of radius 5
+        assert_eq!(circle_outline_coverage(5f32, 5f32, 0.5), 1f32);
+        assert_eq!(circle_outline_coverage(5.5, 5f32, 0.5), 0.5);
+        assert_eq!(circle_outline_coverage(4.5, 5f32, 0.5), 0.5);
+        assert_eq!(circle_outline_coverage(6f32, 5f32, 0.5), 0f32);
+        assert_eq!(circle_outline_coverage(0f32, 5f32, 0.5), 0f32);
+
+        // total coverage of a small circle is close to its circumference
+        let r = 3f32;
+        let mut total = 0f32;
+        for y in -5..=5 {
+            for x in -5..=5 {
+                total += circle_outline_coverage(((x * x + y * y) as f32).sqrt(), r, 0.5);
+            }
+        }
+        assert!((total - 2f32 * std::f32::consts::PI * r).abs() < 1.5, "{}", total);
+    }
+
+    #[test]
+    fn box_downsample_averages_blocks() {
+        let pixels = [
+            0xFF000000, 0xFFFFFFFF, 0xFF102030, 0xFF102030,
+            0x00000000, 0xFF000000, 0xFF102030, 0xFF102030,
+        ];
+        assert_eq!(box_downsample(&pixels, 4, 2, 2), vec![0xBF404040, 0xFF102030]);
+        assert_eq!(box_downsample(&pixels, 4, 2, 1), pixels.to_vec());
+    }
+
+    #[test]
+    fn join_wedges_close_the_outer_corner() {
+        // right angle turn: right along x then down along y (y down)
+        let (a, v, b) = ((0f32, 0f32), (10f32, 0f32), (10f32, 10f32));
+
+        let bevel = join_wedge(a, v, b, 2f32, StrokeJoin::Bevel).unwrap();
+        assert_eq!(bevel, vec![(10f32, 0f32), (10f32, -2f32), (12f32, 0f32)]);
+
+        let miter = join_wedge(a, v, b, 2f32, StrokeJoin::Miter).unwrap();
+        assert_eq!(miter.len(), 4);
+        assert!((miter[2].0 - 12f32).abs() < 1e-4 && (miter[2].1 + 2f32).abs() < 1e-4);
+
+        // hairpin turns fall back to a bevel, straight lines and Round joins need no wedge
+        assert_eq!(join_wedge(a, v, (0f32, 1f32), 2f32, StrokeJoin::Miter).unwrap().len(), 3);
+        assert!(join_wedge(a, v, (20f32, 0f32), 2f32, StrokeJoin::Miter).is_none());
+        assert!(join_wedge(a, v, b, 2f32, StrokeJoin::Round).is_none());
+    }
+
+    /// Compares drawing a dense spline with the stroke mask regenerated for every segment (as before the cache)
+    /// and with a single cached mask, run with
+    /// cargo test --release -- --ignored --nocapture stroke_mask_cache_benchmark
+    #[test]
+    #[ignore]
+    fn stroke_mask_cache_benchmark() {
+        let points = Sketch::<MyState>::cubic_bezier_points(
+            (0f32, 0f32), (4000f32, 6000f32), (-2000f32, 6000f32), (2000f32, 0f32), 0.01,
+        );
+        let weight = 6;
+        let cached_mask = circular_mask(weight);
+
+        // traces every segment and stamps the mask on its pixels, like Sketch::line does, for 100 frames
+        let draw_spline = |regenerate: bool| {
+            let mut checksum = 0i64;
+            for segment in points.windows(2).cycle().take(100 * (points.len() - 1)) {
+                let regenerated_mask;
+                let mask = if regenerate {
+                    regenerated_mask = circular_mask(weight);
+                    &regenerated_mask
+                } else {
+                    &cached_mask
+                };
+                let (_, traced) = bresenham_line_clipped(segment[0].0, segment[0].1, segment[1].0, segment[1].1, 8000, 8000, weight);
+                for (x, y) in traced {
+                    for (i, j) in mask {
+                        checksum += (x + i) as i64 ^ (y + j) as i64;
+                    }
+                }
+            }
+            std::hint::black_box(checksum)
+        };
+
+        let start = std::time::Instant::now();
+        let regenerated = draw_spline(true);
+        let regenerated_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let cached = draw_spline(false);
+        let cached_time = start.elapsed();
+
+        assert_eq!(regenerated, cached);
+        println!("100 x {} segments, weight {} : regenerated mask {:?}, cached mask {:?}",
+                 points.len() - 1, weight, regenerated_time, cached_time);
+    }
+
+    #[test]
+    fn framerate_is_not_bounded_by_the_delta_time_clamp() {
+        assert_eq!(framerate_from_frame_time(0.0), 0.0);
+        assert_eq!(framerate_from_frame_time(0.5), 2.0);
+        assert_eq!(framerate_from_frame_time(0.02), 50.0);
+    }
+
+    #[test]
+    fn recordings_keep_frames_of_different_sizes() {
+        use image::AnimationDecoder;
+
+        let small = (2, 2, vec![0xFFFF0000; 4]);
+        let large = (4, 3, vec![0xFF0000FF; 12]);
+        let file_path = std::env::temp_dir().join("rust_render_101_resized_recording.gif");
+        let file_path = file_path.to_str().unwrap();
+        encode_gif(file_path, &[small, large], 30).unwrap();
+
+        let file = std::io::BufReader::new(fs::File::open(file_path).unwrap());
+        let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+        fs::remove_file(file_path).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        let (first, second) = (frames[0].buffer(), frames[1].buffer());
+        assert_eq!(first.dimensions(), (4, 3));
+        assert_eq!(first.get_pixel(1, 1).0, [255, 0, 0, 255]);
+        assert_eq!(first.get_pixel(3, 2).0, [0, 0, 0, 255]);
+        assert_eq!(second.get_pixel(3, 2).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn graphics_pixels_are_read_through_the_shared_buffer() {
+        let mut graphics = Graphics::new(3, 2);
+        graphics.buffer.pixels[4] = 0xFF123456;
+
+        assert_eq!(graphics.get_pixel(1, 1), Some(0xFF123456));
+        assert_eq!(graphics.get_pixel(0, 0), Some(0));
+        assert_eq!(graphics.get_pixel(3, 0), None);
+        assert_eq!(graphics.get_pixel(0, -1), None);
+    }
+
+    #[test]
+    fn huge_rects_saturate_instead_of_overflowing() {
+        assert_eq!(normalize_rect(10, 0, i32::MAX, 10), (10, 0, i32::MAX, 10));
+        assert_eq!(normalize_rect(-10, 5, i32::MIN, -10), (i32::MIN, -5, i32::MAX, 10));
+        assert_eq!(clip_region(10, 0, i32::MAX, 10, 40, 30), Some((10, 0, 30, 10)));
+        assert_eq!(clip_region(i32::MAX, i32::MAX, i32::MAX, i32::MAX, 40, 30), None);
+
+        // the edges of such a rect are traced across the window without overflowing
+        let (first_position, points) = bresenham_line_clipped(-5, 3, i32::MAX, 3, 40, 30, 0);
+        assert_eq!(points[0], (-5 + first_position as i32, 3));
+        assert!(points.contains(&(0, 3)) && points.contains(&(39, 3)));
+        let (_, points) = bresenham_line_clipped(i32::MIN, i32::MIN, i32::MAX, i32::MAX, 40, 30, 0);
+        assert!(points.contains(&(0, 0)) && points.contains(&(29, 29)));
+    }
+
+    #[test]
+    fn blur_radius_is_clamped_to_the_region() {
+        let source: Vec<u32> = (0..6 * 4).map(|k| 0xFF000000 | (k * 10)).collect();
+
+        let mut huge = source.clone();
+        box_blur_region(&mut huge, 6, (1, 1, 4, 2), i32::MAX as usize);
+        let mut clamped = source.clone();
+        box_blur_region(&mut clamped, 6, (1, 1, 4, 2), 4);
+        assert_eq!(huge, clamped);
+
+        // pixels outside the region are left as they were
+        assert_eq!(huge[0..7], source[0..7]);
+        assert_ne!(huge[7], source[7]);
+    }
+
+    #[test]
+    fn graphics_are_drawn_without_the_camera() {
+        // needs a window, skipped where none can be opened
+        let Ok(mut sketch) = Sketch::<MyState>::try_from_size(32, 32, MyState::default()) else {return;};
+        sketch.camera_translate(10f32, 5f32);
+        sketch.camera_zoom(2f32);
+        let camera = sketch.get_camera();
+
+        let mut graphics = Graphics::new(8, 8);
+        sketch.draw_into(&mut graphics, |sketch| {
+            sketch.no_stroke();
+            sketch.fill(0xFFFF0000);
+            sketch.rect(1, 1, 2, 2);
+        });
+
+        assert_eq!(graphics.get_pixel(1, 1), Some(0xFFFF0000));
+        assert_eq!(graphics.get_pixel(2, 2), Some(0xFFFF0000));
+        assert_eq!(graphics.get_pixel(0, 0), Some(0));
+        assert_eq!(graphics.get_pixel(3, 3), Some(0));
+        assert_eq!(sketch.get_camera(), camera);
+    }
+
+    #[test]
+    fn blur_leaves_pixels_outside_the_clip_rect() {
+        let source: Vec<u32> = (0..32 * 32).map(|k| if (k + k / 32) % 2 == 0 {0xFFFFFFFF} else {0xFF000000}).collect();
+        let outside = |pixels: &[u32]| -> Vec<u32> {
+            pixels.iter().enumerate()
+                .filter(|(k, _)| !(8..16).contains(&(k % 32)) || !(8..16).contains(&(k / 32)))
+                .map(|(_, &color)| color).collect()
+        };
+
+        assert_eq!(intersect_region((0, 0, 32, 32), Some((8, 8, 8, 8))), Some((8, 8, 8, 8)));
+        assert_eq!(intersect_region((0, 0, 4, 4), Some((8, 8, 8, 8))), None);
+        assert_eq!(intersect_region((0, 0, 4, 4), None), Some((0, 0, 4, 4)));
+
+        // the region Sketch::blur passes to the box passes under clip(8, 8, 8, 8)
+        let mut pixels = source.clone();
+        let region = clip_region(0, 0, 32, 32, 32, 32).and_then(|region| intersect_region(region, Some((8, 8, 8, 8)))).unwrap();
+        box_blur_region(&mut pixels, 32, region, 2);
+        assert_eq!(outside(&pixels), outside(&source));
+        assert_ne!(pixels, source);
+
+        // needs a window, skipped where none can be opened
+        let Ok(mut sketch) = Sketch::<MyState>::try_from_size(32, 32, MyState::default()) else {return;};
+        sketch.set_pixels(&source);
+        sketch.clip(8, 8, 8, 8);
+        sketch.blur(0, 0, 32, 32, 2);
+        assert_eq!(outside(sketch.pixels()), outside(&source));
+        assert_ne!(sketch.pixels(), &source[..]);
+    }
+
+    #[test]
+    fn smooth_circles_centered_far_off_screen_do_not_overflow() {
+        // needs a window, skipped where none can be opened
+        let Ok(mut sketch) = Sketch::<MyState>::try_from_size(32, 32, MyState::default()) else {return;};
+        sketch.smooth_circles(true);
+        sketch.no_fill();
+        sketch.stroke(0xFFFFFFFF);
+
+        // the band crosses the window, 100000 pixels from the center
+        sketch.circle(16, 100_016, 100_000);
+        assert!(sketch.pixels().iter().any(|&color| color != 0));
+    }
+
+    #[test]
+    fn triangle_spans_have_no_seams() {
+        // two triangles sharing a diagonal cover a rectangle exactly once
+        let mut covered: Vec<u32> = vec![0; 37 * 23];
+        let mut count = |y: i32, x_start: i32, x_end: i32| {
+            for x in x_start..=x_end {
+                covered[(x + y * 37) as usize] += 1;
+            }
+        };
+        triangle_scanline_spans((0, 0), (37, 0), (37, 23), &mut count);
+        triangle_scanline_spans((0, 0), (37, 23), (0, 23), &mut count);
+        assert!(covered.iter().all(|&c| c == 1));
+
+        // a single triangle covers close to its analytic area
+        let mut filled = 0;
+        triangle_scanline_spans((5, 3), (61, 20), (17, 47), |_, x_start, x_end| {
+            filled += x_end - x_start + 1;
+        });
+        let area = ((61 - 5) * (47 - 3) - (17 - 5) * (20 - 3)) as f32 / 2f32;
+        assert!((filled as f32 - area).abs() <= 2f32);
+    }
 }